@@ -1,4 +1,4 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use radius::Client;
 use radius::Config;
 use radius::Credentials;
@@ -13,31 +13,129 @@ struct Cli {
     )]
     config: std::path::PathBuf,
 
+    /// Required to authenticate
     #[structopt(short = "u", long = "username")]
-    username: String,
+    username: Option<String>,
 
+    /// Required to authenticate
     #[structopt(short = "p", long = "password")]
-    password: String,
+    password: Option<String>,
+
+    /// Write sent and received RADIUS packets to a pcap file
+    #[structopt(parse(from_os_str), long = "pcap")]
+    pcap: Option<std::path::PathBuf>,
+
+    #[cfg(unix)]
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[cfg(unix)]
+#[derive(StructOpt)]
+enum Command {
+    /// Explain how the user is mapped to a local account, with every
+    /// mapping rule that was evaluated. The mapping.command program is run
+    /// if it is configured.
+    Map {
+        /// Read the user from a JSON file, as printed by this client, instead
+        /// of authenticating
+        #[structopt(parse(from_os_str), long = "attributes")]
+        attributes: Option<std::path::PathBuf>,
+
+        /// Drop-in directory merged after the configuration file, defaults
+        /// to the system one for the system configuration file
+        #[structopt(parse(from_os_str), short = "d", long = "dir")]
+        dir: Option<std::path::PathBuf>,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Cli::from_args();
-    let config = Config::read_file(&args.config).context(format!(
+
+    #[cfg(unix)]
+    {
+        if let Some(Command::Map { attributes, dir }) = &args.command {
+            return map(&args, attributes.as_deref(), dir.as_deref());
+        }
+    }
+
+    let mut config = Config::read_file(&args.config).context(format!(
         "Cannot read configuration from {}",
         args.config.to_string_lossy()
     ))?;
 
-    let client = Client::with_config(&config)
-        .context("Cannot initialize client with config")?;
+    init_logger(config.debug.unwrap_or(false));
 
-    let cred =
-        Credentials::with_username_password(args.username, args.password);
-    let user = client
-        .authenticate(&cred)
-        .context("Authentication failure")?;
+    let user = authenticate(&mut config, &args)?;
 
     let j = serde_json::to_string(&user)?;
     println!("{}", j);
 
     Ok(())
 }
+
+fn init_logger(debug: bool) {
+    let mut logger = env_logger::Builder::from_default_env();
+    if debug {
+        logger.filter_level(log::LevelFilter::Debug);
+    }
+    logger.init();
+}
+
+fn authenticate(config: &mut Config, args: &Cli) -> Result<radius::User> {
+    let (username, password) = match (&args.username, &args.password) {
+        (Some(username), Some(password)) => (username, password),
+        _ => return Err(anyhow!("--username and --password are required")),
+    };
+
+    if let Some(pcap) = &args.pcap {
+        config.trace_file = Some(pcap.clone());
+    }
+
+    let client = Client::with_config(config)
+        .context("Cannot initialize client with config")?;
+
+    let cred = Credentials::with_username_password(username, password);
+    client.authenticate(&cred).context("Authentication failure")
+}
+
+#[cfg(unix)]
+fn map(
+    args: &Cli,
+    attributes: Option<&std::path::Path>,
+    dir: Option<&std::path::Path>,
+) -> Result<()> {
+    use nss_db::Config;
+
+    let system =
+        args.config.as_path() == std::path::Path::new(Config::system_path());
+    let config = match dir {
+        Some(dir) => Config::read_with_dir(&args.config, dir),
+        None if system => {
+            Config::read_with_dir(&args.config, Config::system_dir())
+        }
+        None => Config::read_file(&args.config),
+    };
+    let mut config = config.context(format!(
+        "Cannot read configuration from {}",
+        args.config.to_string_lossy()
+    ))?;
+
+    init_logger(config.debug());
+
+    let user = match attributes {
+        Some(path) => {
+            let file = std::fs::File::open(path)
+                .context(format!("Cannot open {}", path.to_string_lossy()))?;
+            serde_json::from_reader(file).context(format!(
+                "Cannot read user from {}",
+                path.to_string_lossy()
+            ))?
+        }
+        None => authenticate(&mut config.radius, args)?,
+    };
+
+    print!("{}", config.explain_mapping(&user));
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use anyhow::{bail, Result};
+use nss_db::Config;
+use nss_db::Error;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "radius_virtual")]
+enum Cli {
+    /// Check a configuration file, unknown fields and insecure permissions
+    /// are errors
+    CheckConfig {
+        /// Defaults to the system configuration file and drop-in directory
+        #[structopt(parse(from_os_str), short = "c", long = "config")]
+        config: Option<PathBuf>,
+
+        /// Drop-in directory merged after the configuration file
+        #[structopt(parse(from_os_str), short = "d", long = "dir")]
+        dir: Option<PathBuf>,
+    },
+}
+
+fn main() -> Result<()> {
+    match Cli::from_args() {
+        Cli::CheckConfig { config, dir } => {
+            let dir = match (&config, dir) {
+                (_, Some(dir)) => Some(dir),
+                (None, None) => Some(Config::system_dir().into()),
+                (Some(_), None) => None,
+            };
+            let config = config.unwrap_or_else(|| Config::system_path().into());
+            check_config(config, dir)
+        }
+    }
+}
+
+fn check_config(path: PathBuf, dir: Option<PathBuf>) -> Result<()> {
+    let config = match dir {
+        Some(dir) => Config::read_with_dir_strict(&path, dir),
+        None => Config::read_file_strict(&path),
+    };
+
+    let res = config.and_then(|config| {
+        config.check_permissions()?;
+        config.validate()?;
+        Ok(config)
+    });
+
+    match res {
+        Ok(config) => {
+            for file in config.sources.files() {
+                println!("{}: OK", file.display());
+            }
+            Ok(())
+        }
+        Err(Error::InvalidConfig(errors)) => report(errors.iter()),
+        Err(Error::UnknownFields(fields)) => report(fields.iter()),
+        Err(err) => report(std::iter::once(err)),
+    }
+}
+
+fn report<I, E>(errors: I) -> Result<()>
+where
+    I: Iterator<Item = E>,
+    E: std::fmt::Display,
+{
+    let mut count = 0;
+    for err in errors {
+        eprintln!("{}", err);
+        count += 1;
+    }
+    bail!("{} error(s) found in configuration", count)
+}
@@ -0,0 +1,160 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Well known vendor ids, names are matched case insensitively
+const VENDORS: &[(&str, u32)] = &[
+    ("cisco", 9),
+    ("microsoft", 311),
+    ("juniper", 2636),
+    ("freeradius", 11344),
+    ("fortinet", 12356),
+    ("aruba", 14823),
+    ("mikrotik", 14988),
+    ("paloalto", 25461),
+];
+
+/// Well known vendor attributes, names are matched case insensitively
+const ATTRIBUTES: &[(&str, u32, u8)] = &[
+    ("cisco-avpair", 9, 1),
+    ("juniper-local-user-name", 2636, 1),
+    ("fortinet-group-name", 12356, 1),
+    ("aruba-user-role", 14823, 1),
+    ("mikrotik-group", 14988, 3),
+    ("paloalto-user-group", 25461, 5),
+];
+
+/// Standard attributes (RFC 2865), names are matched case insensitively
+const STANDARD_ATTRIBUTES: &[(&str, u8)] = &[
+    ("service-type", 6),
+    ("filter-id", 11),
+    ("reply-message", 18),
+    ("class", 25),
+    ("session-timeout", 27),
+    ("idle-timeout", 28),
+    ("framed-pool", 88),
+];
+
+/// Vendor used for standard attributes, which are not vendor specific
+pub const STANDARD_VENDOR: u32 = 0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseAttrErrorKind {
+    /// The spec is neither `vendor.subtype`, a standard attribute number
+    /// nor a known attribute name
+    Format,
+    /// The vendor is neither a number nor a known vendor name
+    Vendor,
+    /// The subtype is not a number between 0 and 255, or the standard
+    /// attribute not between 1 and 255
+    Subtype,
+}
+
+/// Error returned by `parse_attr`, it includes the offending spec
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseAttrError {
+    pub spec: String,
+    pub kind: ParseAttrErrorKind,
+}
+
+impl fmt::Display for ParseAttrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            ParseAttrErrorKind::Format => {
+                "expected `vendor.subtype`, a standard attribute number or a \
+                 known attribute name"
+            }
+            ParseAttrErrorKind::Vendor => {
+                "vendor must be a number or a known vendor name"
+            }
+            ParseAttrErrorKind::Subtype => {
+                "subtype must be a number between 0 and 255, or the \
+                 standard attribute between 1 and 255"
+            }
+        };
+        write!(f, "invalid attribute `{}`: {}", self.spec, reason)
+    }
+}
+
+impl std::error::Error for ParseAttrError {}
+
+/// Parse an attribute spec.
+///
+/// The spec is either `vendor.subtype`, a standard attribute number or a known
+/// attribute name like `Cisco-AVPair` or `Class`. Numbers are decimal or
+/// hexadecimal with a `0x` prefix, the vendor can also be a known vendor name
+/// like `cisco`. Standard attributes use `STANDARD_VENDOR` as vendor.
+pub fn parse_attr(spec: &str) -> Result<(u32, u8), ParseAttrError> {
+    let err = |kind| ParseAttrError {
+        spec: spec.to_string(),
+        kind,
+    };
+
+    let trimmed = spec.trim();
+
+    if let Some((_, vendor, subtype)) = ATTRIBUTES
+        .iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(trimmed))
+    {
+        return Ok((*vendor, *subtype));
+    }
+
+    if let Some((_, attr)) = STANDARD_ATTRIBUTES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(trimmed))
+    {
+        return Ok((STANDARD_VENDOR, *attr));
+    }
+
+    if !trimmed.contains('.') {
+        return match parse_number(trimmed) {
+            Some(attr) => u8::try_from(attr)
+                .ok()
+                .filter(|attr| *attr != 0)
+                .map(|attr| (STANDARD_VENDOR, attr))
+                .ok_or_else(|| err(ParseAttrErrorKind::Subtype)),
+            None => Err(err(ParseAttrErrorKind::Format)),
+        };
+    }
+
+    let mut parts = trimmed.split('.');
+
+    let (vendor, subtype) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(vendor), Some(subtype), None) => (vendor, subtype),
+        _ => return Err(err(ParseAttrErrorKind::Format)),
+    };
+
+    let vendor = parse_number(vendor)
+        .or_else(|| {
+            VENDORS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(vendor))
+                .map(|(_, id)| *id)
+        })
+        .ok_or_else(|| err(ParseAttrErrorKind::Vendor))?;
+
+    let subtype = parse_number(subtype)
+        .and_then(|subtype| u8::try_from(subtype).ok())
+        .ok_or_else(|| err(ParseAttrErrorKind::Subtype))?;
+
+    Ok((vendor, subtype))
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal number, signs and
+/// whitespace are rejected.
+fn parse_number(s: &str) -> Option<u32> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"));
+
+    match hex {
+        Some(hex)
+            if !hex.is_empty()
+                && hex.bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            u32::from_str_radix(hex, 16).ok()
+        }
+        Some(_) => None,
+        None if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => {
+            s.parse().ok()
+        }
+        None => None,
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use toml::value::{Table, Value};
+
+/// Key present in a config file but not used by the configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownField {
+    /// Full key, array indices are written `[n]`
+    pub key: String,
+    /// File the key was read from, when the config has several files
+    pub path: Option<PathBuf>,
+    /// 1-based line and column of the key, if it could be found
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown field `{}`", self.key)?;
+        if let Some(path) = &self.path {
+            write!(f, " in {}", path.display())?;
+        }
+        if let Some((line, column)) = self.location {
+            write!(f, " at line {} column {}", line, column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize a TOML config and collect the keys that were ignored
+pub fn from_str<T: DeserializeOwned>(
+    text: &str,
+) -> Result<(T, Vec<UnknownField>), toml::de::Error> {
+    let mut paths = vec![];
+
+    let mut de = toml::Deserializer::new(text);
+    let value = serde_ignored::deserialize(&mut de, |path| {
+        paths.push(segments(&path));
+    })?;
+
+    let unknown = paths
+        .into_iter()
+        .map(|segments| UnknownField {
+            key: format_key(&segments),
+            path: None,
+            location: locate(text, &segments),
+        })
+        .collect();
+
+    Ok((value, unknown))
+}
+
+/// Config merged from several files, later files override the settings of
+/// earlier ones.
+///
+/// Tables are merged recursively, arrays listed as appended are concatenated
+/// and any other value is replaced.
+pub struct Layers<'a> {
+    appended: Vec<Vec<Segment>>,
+    value: Table,
+    texts: Vec<&'a str>,
+    sources: Sources,
+}
+
+impl<'a> Layers<'a> {
+    /// Create an empty config, `appended` lists the keys of arrays which are
+    /// concatenated, like `mapping.users`
+    pub fn new(appended: &[&str]) -> Self {
+        Layers {
+            appended: appended.iter().map(|key| parse_key(key)).collect(),
+            value: Table::new(),
+            texts: vec![],
+            sources: Sources::default(),
+        }
+    }
+
+    /// Merge a file into the config
+    pub fn add(
+        &mut self,
+        path: &Path,
+        text: &'a str,
+    ) -> Result<(), toml::de::Error> {
+        let table: Table = toml::from_str(text)?;
+        let mut value = std::mem::take(&mut self.value);
+
+        let mut merge = Merge {
+            file: self.texts.len(),
+            appended: &self.appended,
+            origins: &mut self.sources.origins,
+        };
+        merge.table(&mut value, table, &mut vec![]);
+
+        self.value = value;
+        self.texts.push(text);
+        self.sources.files.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Deserialize the merged config and collect the keys that were ignored
+    pub fn deserialize<T: DeserializeOwned>(
+        mut self,
+    ) -> Result<(T, Vec<UnknownField>, Sources), MergeError> {
+        let mut paths = vec![];
+
+        let table = std::mem::take(&mut self.value);
+        let result = serde_ignored::deserialize(Value::Table(table), |path| {
+            paths.push(segments(&path));
+        });
+        let value = result.map_err(|err| self.locate_error::<T>(err))?;
+
+        let texts = self.texts;
+        let sources = self.sources;
+
+        let unknown = paths
+            .into_iter()
+            .map(|segments| {
+                let origin = sources.origin(&segments);
+                UnknownField {
+                    key: format_key(&segments),
+                    path: origin
+                        .as_ref()
+                        .map(|(file, _)| sources.files[*file].clone()),
+                    location: origin
+                        .and_then(|(file, key)| locate(texts[file], &key)),
+                }
+            })
+            .collect();
+
+        Ok((value, unknown, sources))
+    }
+
+    /// Find the file of an error of the merged config, the merged value has
+    /// no location. Each file is deserialized alone, the first one failing
+    /// for another reason than a missing field, which may be set by another
+    /// file, has the error with its line and column. A single file always
+    /// has it.
+    fn locate_error<T: DeserializeOwned>(
+        &self,
+        err: toml::de::Error,
+    ) -> MergeError {
+        for (path, text) in self.sources.files.iter().zip(self.texts.iter()) {
+            match toml::from_str::<T>(text) {
+                Err(err)
+                    if self.texts.len() == 1
+                        || !err.to_string().starts_with("missing field") =>
+                {
+                    return MergeError {
+                        path: Some(path.clone()),
+                        error: err,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        MergeError {
+            path: None,
+            error: err,
+        }
+    }
+}
+
+/// Error of a merged config, with the file it was found in when it could be
+/// located
+#[derive(Debug)]
+pub struct MergeError {
+    pub path: Option<PathBuf>,
+    pub error: toml::de::Error,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// Files a config was read from and the file each setting came from
+#[derive(Clone, Debug, Default)]
+pub struct Sources {
+    files: Vec<PathBuf>,
+    /// Merged key of each value and where it was read, array elements of
+    /// appended arrays have their own origin.
+    origins: Vec<(Vec<Segment>, Origin)>,
+}
+
+#[derive(Clone, Debug)]
+struct Origin {
+    file: usize,
+    key: Vec<Segment>,
+}
+
+impl Sources {
+    /// Files in the order they were merged
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// File which set a key like `radius.shared_secret` or
+    /// `mapping.users[2]`
+    pub fn file_of(&self, key: &str) -> Option<&Path> {
+        self.origin(&parse_key(key))
+            .map(|(file, _)| self.files[file].as_path())
+    }
+
+    /// File index and key in that file of a merged key
+    fn origin(&self, key: &[Segment]) -> Option<(usize, Vec<Segment>)> {
+        self.origins
+            .iter()
+            .filter(|(merged, _)| key.starts_with(merged))
+            .max_by_key(|(merged, _)| merged.len())
+            .map(|(merged, origin)| {
+                let mut local = origin.key.clone();
+                local.extend_from_slice(&key[merged.len()..]);
+                (origin.file, local)
+            })
+    }
+}
+
+struct Merge<'a> {
+    file: usize,
+    appended: &'a [Vec<Segment>],
+    origins: &'a mut Vec<(Vec<Segment>, Origin)>,
+}
+
+impl<'a> Merge<'a> {
+    fn table(
+        &mut self,
+        into: &mut Table,
+        from: Table,
+        prefix: &mut Vec<Segment>,
+    ) {
+        for (k, v) in from {
+            prefix.push(Segment::Key(k.clone()));
+
+            match v {
+                Value::Table(src) => {
+                    if !matches!(into.get(&k), Some(Value::Table(_))) {
+                        self.clear(prefix);
+                        into.insert(k.clone(), Value::Table(Table::new()));
+                    }
+                    if let Some(Value::Table(dst)) = into.get_mut(&k) {
+                        self.table(dst, src, prefix);
+                    }
+                }
+                Value::Array(src) if self.appended.contains(prefix) => {
+                    if !matches!(into.get(&k), Some(Value::Array(_))) {
+                        self.clear(prefix);
+                        into.insert(k.clone(), Value::Array(vec![]));
+                    }
+                    if let Some(Value::Array(dst)) = into.get_mut(&k) {
+                        for (i, item) in src.into_iter().enumerate() {
+                            let mut merged = prefix.clone();
+                            merged.push(Segment::Index(dst.len()));
+                            let mut local = prefix.clone();
+                            local.push(Segment::Index(i));
+                            self.set(merged, local);
+                            dst.push(item);
+                        }
+                    }
+                }
+                v => {
+                    self.clear(prefix);
+                    self.set(prefix.clone(), prefix.clone());
+                    into.insert(k, v);
+                }
+            }
+
+            prefix.pop();
+        }
+    }
+
+    fn set(&mut self, merged: Vec<Segment>, key: Vec<Segment>) {
+        self.origins.push((
+            merged,
+            Origin {
+                file: self.file,
+                key,
+            },
+        ));
+    }
+
+    fn clear(&mut self, prefix: &[Segment]) {
+        self.origins
+            .retain(|(merged, _)| !merged.starts_with(prefix));
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn segments(path: &serde_ignored::Path) -> Vec<Segment> {
+    use serde_ignored::Path;
+
+    let mut segments = match path {
+        Path::Root => return vec![],
+        Path::Seq { parent, .. }
+        | Path::Map { parent, .. }
+        | Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => segments(parent),
+    };
+
+    match path {
+        Path::Seq { index, .. } => segments.push(Segment::Index(*index)),
+        Path::Map { key, .. } => segments.push(Segment::Key(key.clone())),
+        _ => {}
+    }
+
+    segments
+}
+
+fn format_key(segments: &[Segment]) -> String {
+    let mut key = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Key(k) if key.is_empty() => key.push_str(k),
+            Segment::Key(k) => {
+                key.push('.');
+                key.push_str(k);
+            }
+            Segment::Index(i) => key.push_str(&format!("[{}]", i)),
+        }
+    }
+    key
+}
+
+/// Find the line of a key by following table headers, dotted keys and
+/// inline tables are not supported.
+fn locate(text: &str, segments: &[Segment]) -> Option<(usize, usize)> {
+    let mut table: Vec<Segment> = vec![];
+    let mut array_counts: HashMap<String, usize> = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+
+        if trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = trimmed.strip_prefix("[[") {
+            let name = header.split("]]").next()?.trim();
+            let count = array_counts.entry(name.to_string()).or_insert(0);
+            table = split_key(name);
+            table.push(Segment::Index(*count));
+            *count += 1;
+        } else if let Some(header) = trimmed.strip_prefix('[') {
+            table = split_key(header.split(']').next()?.trim());
+        } else if let Some(eq) = trimmed.find('=') {
+            let mut key = table.clone();
+            key.extend(split_key(trimmed[..eq].trim()));
+            if key == segments {
+                return Some((i + 1, column));
+            }
+        }
+    }
+
+    None
+}
+
+/// Parse a key like `mapping.users[2].uid`
+fn parse_key(key: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    for part in key.split('.') {
+        let mut parts = part.split('[');
+        if let Some(name) = parts.next().filter(|name| !name.is_empty()) {
+            segments.push(Segment::Key(name.to_string()));
+        }
+        for index in parts {
+            if let Ok(i) = index.trim_end_matches(']').parse() {
+                segments.push(Segment::Index(i));
+            }
+        }
+    }
+    segments
+}
+
+fn split_key(key: &str) -> Vec<Segment> {
+    key.split('.')
+        .map(|k| Segment::Key(k.trim().trim_matches('"').to_string()))
+        .collect()
+}
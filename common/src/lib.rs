@@ -1 +1,3 @@
+pub mod attr;
+pub mod config;
 pub mod serde;
@@ -1,5 +1,7 @@
 use serde::{Deserializer, Serializer};
 
+use crate::attr::parse_attr;
+
 pub fn encode_base16<T, S>(key: &T, serializer: S) -> Result<S::Ok, S::Error>
 where
     T: AsRef<[u8]>,
@@ -20,41 +22,40 @@ where
 pub fn decode_attrs<'de, D: Deserializer<'de>>(
     deserializer: D,
 ) -> Result<Option<Vec<(u32, u8)>>, D::Error> {
-    let str_attrs: Vec<String> =
+    let attrs: Vec<AttrSpec> =
         serde::de::Deserialize::deserialize(deserializer)?;
-
-    let mut attrs: Vec<(u32, u8)> = vec![];
-
-    for attr in str_attrs.iter() {
-        if let Ok(attr) = parse_attr(attr) {
-            attrs.push(attr);
-        }
-    }
-    Ok(Some(attrs))
+    Ok(Some(attrs.into_iter().map(|attr| attr.0).collect()))
 }
 
 pub fn decode_attr<'de, D: Deserializer<'de>>(
     deserializer: D,
 ) -> Result<(u32, u8), D::Error> {
-    let str_attr: String = serde::de::Deserialize::deserialize(deserializer)?;
+    deserializer.deserialize_str(AttrVisitor)
+}
 
-    use serde::de::Error;
-    parse_attr(&str_attr).map_err(|err| Error::custom(err.to_string()))
+/// Attribute parsed while its string is read, so that errors have the
+/// location of the string and not of the enclosing table
+struct AttrSpec((u32, u8));
+
+impl<'de> serde::de::Deserialize<'de> for AttrSpec {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(AttrVisitor).map(AttrSpec)
+    }
 }
 
-fn parse_attr(attr: &String) -> Result<(u32, u8), String> {
-    let parts: Vec<&str> = attr.split('.').collect();
-    let err = Err("invalid attribute format".to_string());
-    if parts.len() != 2 {
-        return err;
+struct AttrVisitor;
+
+impl<'de> serde::de::Visitor<'de> for AttrVisitor {
+    type Value = (u32, u8);
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an attribute like `vendor.subtype` or a name")
     }
-    let vendor = parts.get(0).unwrap();
-    let subtype = parts.get(1).unwrap();
 
-    if let (Ok(vendor), Ok(subtype)) = (vendor.parse(), subtype.parse()) {
-        Ok((vendor, subtype))
-    } else {
-        err
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse_attr(v).map_err(E::custom)
     }
 }
 
@@ -65,3 +66,22 @@ where
     let s = format!("{}.{}", key.0, key.1);
     serializer.serialize_str(&s)
 }
+
+pub fn decode_opt_attr<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<(u32, u8)>, D::Error> {
+    decode_attr(deserializer).map(Some)
+}
+
+pub fn encode_opt_attr<S>(
+    key: &Option<(u32, u8)>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match key {
+        Some(key) => encode_attr(key, serializer),
+        None => serializer.serialize_none(),
+    }
+}
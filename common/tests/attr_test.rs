@@ -0,0 +1,100 @@
+use common::attr::{parse_attr, ParseAttrErrorKind};
+use common::serde::decode_attrs;
+use proptest::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Config {
+    #[serde(deserialize_with = "decode_attrs")]
+    attributes: Option<Vec<(u32, u8)>>,
+}
+
+proptest! {
+    #[test]
+    fn it_parses_decimal(vendor: u32, subtype: u8) {
+        let spec = format!("{}.{}", vendor, subtype);
+        prop_assert_eq!(parse_attr(&spec), Ok((vendor, subtype)));
+    }
+
+    #[test]
+    fn it_parses_hex(vendor: u32, subtype: u8) {
+        let spec = format!("0x{:x}.0X{:X}", vendor, subtype);
+        prop_assert_eq!(parse_attr(&spec), Ok((vendor, subtype)));
+    }
+
+    #[test]
+    fn it_rejects_large_subtype(vendor: u32, subtype in 256u32..) {
+        let spec = format!("{}.{}", vendor, subtype);
+        let err = parse_attr(&spec).unwrap_err();
+        prop_assert_eq!(err.kind, ParseAttrErrorKind::Subtype);
+    }
+
+    #[test]
+    fn it_rejects_other_separators(
+        vendor: u32,
+        subtype: u8,
+        sep in "[^.0-9a-zA-Z]",
+    ) {
+        let spec = format!("{}{}{}", vendor, sep, subtype);
+        prop_assert!(parse_attr(&spec).is_err());
+    }
+
+    #[test]
+    fn it_never_panics(spec in "\\PC*") {
+        let _ = parse_attr(&spec);
+    }
+
+    #[test]
+    fn it_reports_the_spec(spec in "[0-9]*,[0-9]*") {
+        let err = parse_attr(&spec).unwrap_err();
+        prop_assert!(err.to_string().contains(&spec));
+    }
+}
+
+#[test]
+fn it_parses_names() {
+    assert_eq!(parse_attr("Cisco-AVPair"), Ok((9, 1)));
+    assert_eq!(parse_attr("microsoft.0x1a"), Ok((311, 26)));
+    assert_eq!(
+        parse_attr("acme.1").unwrap_err().kind,
+        ParseAttrErrorKind::Vendor
+    );
+    assert_eq!(
+        parse_attr("+1.1").unwrap_err().kind,
+        ParseAttrErrorKind::Vendor
+    );
+}
+
+#[test]
+fn it_parses_standard_attributes() {
+    assert_eq!(parse_attr("Class"), Ok((0, 25)));
+    assert_eq!(parse_attr("filter-id"), Ok((0, 11)));
+    assert_eq!(parse_attr("88"), Ok((0, 88)));
+    assert_eq!(parse_attr("0x19"), Ok((0, 25)));
+    assert_eq!(
+        parse_attr("0").unwrap_err().kind,
+        ParseAttrErrorKind::Subtype
+    );
+    assert_eq!(
+        parse_attr("256").unwrap_err().kind,
+        ParseAttrErrorKind::Subtype
+    );
+    assert_eq!(
+        parse_attr("Unknown-Attribute").unwrap_err().kind,
+        ParseAttrErrorKind::Format
+    );
+}
+
+#[test]
+fn it_fails_config_loading() {
+    let config: Config =
+        toml::from_str("attributes = [\"1.1\", \"0x9.1\"]").unwrap();
+    assert_eq!(config.attributes, Some(vec![(1, 1), (9, 1)]));
+
+    let err = toml::from_str::<Config>("\nattributes = [\"1.1\", \"1,1\"]")
+        .unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains("`1,1`"), "{}", msg);
+    assert!(msg.contains("attributes"), "{}", msg);
+    assert_eq!(err.line_col().map(|(line, _)| line), Some(1));
+}
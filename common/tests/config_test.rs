@@ -0,0 +1,152 @@
+use common::config::{from_str, Layers, UnknownField};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+struct Server {
+    address: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Radius {
+    shared_secret: Option<String>,
+    servers: Vec<Server>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Config {
+    radius: Radius,
+}
+
+const CONFIG: &str = r#"
+# shared_secrets = "commented"
+[radius]
+shared_secrets = "testing123"
+
+[[radius.servers]]
+address = "127.0.0.1"
+
+[[radius.servers]]
+address = "::1"
+  timeout = 3
+"#;
+
+#[test]
+fn it_reports_unknown_fields() {
+    let (config, unknown) = from_str::<Config>(CONFIG).unwrap();
+
+    assert_eq!(config.radius.shared_secret, None);
+    assert_eq!(config.radius.servers[1].address, "::1");
+    assert_eq!(
+        unknown,
+        vec![
+            UnknownField {
+                key: "radius.shared_secrets".to_string(),
+                path: None,
+                location: Some((4, 1)),
+            },
+            UnknownField {
+                key: "radius.servers[1].timeout".to_string(),
+                path: None,
+                location: Some((11, 3)),
+            },
+        ]
+    );
+    assert_eq!(
+        unknown[1].to_string(),
+        "unknown field `radius.servers[1].timeout` at line 11 column 3"
+    );
+}
+
+#[test]
+fn it_accepts_known_fields() {
+    let (_, unknown) = from_str::<Config>("[radius]\nservers = []\n").unwrap();
+
+    assert!(unknown.is_empty());
+}
+
+#[derive(Deserialize, Debug)]
+struct User {
+    username: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Mapping {
+    users: Vec<User>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LayeredConfig {
+    radius: Radius,
+    mapping: Mapping,
+}
+
+const MAIN: &str = r#"
+[radius]
+shared_secret = "main"
+
+[[radius.servers]]
+address = "127.0.0.1"
+
+[[mapping.users]]
+username = "admin"
+"#;
+
+const TEAM: &str = r#"
+[radius]
+shared_secret = "team"
+
+[[mapping.users]]
+username = "dev"
+
+[[mapping.users]]
+username = "ops"
+uid = 1000
+"#;
+
+#[test]
+fn it_merges_layers() {
+    let mut layers = Layers::new(&["mapping.users"]);
+    layers.add(Path::new("main.toml"), MAIN).unwrap();
+    layers.add(Path::new("team.toml"), TEAM).unwrap();
+
+    let (config, unknown, sources) =
+        layers.deserialize::<LayeredConfig>().unwrap();
+
+    assert_eq!(config.radius.shared_secret.as_deref(), Some("team"));
+    assert_eq!(config.radius.servers.len(), 1);
+
+    let users: Vec<_> = config
+        .mapping
+        .users
+        .iter()
+        .map(|u| u.username.as_str())
+        .collect();
+    assert_eq!(users, vec!["admin", "dev", "ops"]);
+
+    assert_eq!(
+        sources.file_of("radius.shared_secret"),
+        Some(Path::new("team.toml"))
+    );
+    assert_eq!(
+        sources.file_of("radius.servers[0].address"),
+        Some(Path::new("main.toml"))
+    );
+    assert_eq!(
+        sources.file_of("mapping.users[0]"),
+        Some(Path::new("main.toml"))
+    );
+    assert_eq!(
+        sources.file_of("mapping.users[2].username"),
+        Some(Path::new("team.toml"))
+    );
+
+    assert_eq!(
+        unknown,
+        vec![UnknownField {
+            key: "mapping.users[2].uid".to_string(),
+            path: Some("team.toml".into()),
+            location: Some((10, 1)),
+        }]
+    );
+}
@@ -0,0 +1,202 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{Context, Result};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use nss_db::setup_log;
+use nss_db::Config;
+use nss_db::Db;
+use nss_db::Session;
+use radius::{Attribute, DynAuthRequest, DynAuthResult, DynAuthServer};
+
+const SYSLOG_NAME: &str = "radius_dynauth";
+
+fn main() -> Result<()> {
+    setup_log(SYSLOG_NAME);
+
+    let config = Config::system().context("Cannot read system config file")?;
+
+    if config.debug() {
+        log::set_max_level(log::LevelFilter::Debug)
+    }
+
+    let mut db = Db::with_config(&config)
+        .context("Cannot initialize database with config")?;
+
+    let mut server = DynAuthServer::with_config(&config.radius)
+        .context("Cannot start dynamic authorization server")?;
+
+    info!("Waiting for dynamic authorization requests");
+
+    server.run(|req| handle_request(&mut db, req));
+
+    Ok(())
+}
+
+fn handle_request(db: &mut Db, req: DynAuthRequest) -> DynAuthResult {
+    let (coa, username, session_id, attributes) = match req {
+        DynAuthRequest::Disconnect {
+            username,
+            session_id,
+        } => (false, username, session_id, vec![]),
+        DynAuthRequest::CoA {
+            username,
+            session_id,
+            attributes,
+        } => (true, username, session_id, attributes),
+    };
+
+    debug!(
+        "Got {} request for user {:?} session {:?}",
+        if coa { "CoA" } else { "Disconnect" },
+        username,
+        session_id
+    );
+
+    // The session ids of the shell wrapper are local and never sent to the
+    // RADIUS server, sessions are only matched by User-Name.
+    let username = match username {
+        Some(username) => username,
+        None => return DynAuthResult::SessionNotFound,
+    };
+
+    let sessions = match db.find_sessions(&username) {
+        Ok(sessions) => sessions,
+        Err(err) => {
+            error!("Cannot read sessions: {}", err);
+            return DynAuthResult::Failed;
+        }
+    };
+
+    let mut active = vec![];
+
+    for session in sessions {
+        if session.is_running() {
+            active.push(session);
+        } else if let Err(err) = db.delete_session(&session.session_id) {
+            error!("Cannot remove session {}: {}", session.session_id, err);
+        }
+    }
+
+    if active.is_empty() {
+        info!("No active session for user {}", username);
+        return DynAuthResult::SessionNotFound;
+    }
+
+    // A user who is no longer authorized is disconnected
+    if coa {
+        match reauthorize(db, &username, &attributes) {
+            Ok(true) => return DynAuthResult::Success,
+            Ok(false) => {}
+            Err(err) => {
+                error!("Cannot update user {}: {:#}", username, err);
+                return DynAuthResult::Failed;
+            }
+        }
+    }
+
+    disconnect(db, &active);
+
+    // The user is mapped again on the next login, it keeps its dynamic uid
+    // which may own files.
+    if let Err(err) = db.revoke_user(&username) {
+        error!("Cannot revoke user {}: {}", username, err);
+        return DynAuthResult::Failed;
+    }
+
+    DynAuthResult::Success
+}
+
+/// Map the stored user again with the current configuration and the
+/// attributes of the CoA-Request, then update its sessions. The running shell
+/// wrappers take the new session timeout. False when the user is no longer
+/// mapped.
+fn reauthorize(
+    db: &mut Db,
+    username: &str,
+    attributes: &[Attribute],
+) -> Result<bool> {
+    let config = Config::system().context("Cannot read system config file")?;
+    let stored = db.get_user(username).context("Cannot read user")?;
+    let radius = config.merge_attributes(&stored.radius, attributes);
+
+    match config.map_user(&radius) {
+        Some(user) => {
+            info!("Updating sessions of {}", username);
+            db.update_user(&user).context("Cannot store user")?;
+            Ok(true)
+        }
+        None => {
+            info!("User {} is no longer authorized", username);
+            Ok(false)
+        }
+    }
+}
+
+fn disconnect(db: &mut Db, sessions: &[Session]) {
+    for session in sessions {
+        info!(
+            "Terminating session {} of {}",
+            session.session_id, session.username
+        );
+        terminate(session);
+        if let Err(err) = db.delete_session(&session.session_id) {
+            error!("Cannot remove session {}: {}", session.session_id, err);
+        }
+    }
+}
+
+/// Hang up every process of the login session started by the shell wrapper
+fn terminate(session: &Session) {
+    let pid = session.pid as libc::pid_t;
+    let sid = unsafe { libc::getsid(pid) };
+
+    let mut pids = vec![pid];
+
+    if sid == pid {
+        pids.extend(session_processes(sid));
+    }
+
+    for pid in pids {
+        if let Err(err) = kill(Pid::from_raw(pid), Signal::SIGHUP) {
+            debug!("Cannot signal process {}: {}", pid, err);
+        }
+    }
+}
+
+fn session_processes(sid: libc::pid_t) -> Vec<libc::pid_t> {
+    let mut pids = vec![];
+
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return pids,
+    };
+
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_string_lossy().parse() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+
+        let stat = match std::fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+
+        // The command name can contain spaces, fields start after it:
+        // state ppid pgrp session ...
+        let fields = match stat.rfind(')') {
+            Some(pos) => &stat[pos + 1..],
+            None => continue,
+        };
+
+        let session = fields.split_whitespace().nth(3);
+
+        if session.and_then(|s| s.parse().ok()) == Some(sid) && pid != sid {
+            pids.push(pid);
+        }
+    }
+
+    pids
+}
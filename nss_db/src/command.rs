@@ -0,0 +1,185 @@
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+use crate::config::{MappingCommand, UserMapping};
+use crate::mapping::is_valid_username;
+
+/// Default seconds after which the program is killed
+const DEFAULT_TIMEOUT: u64 = 5;
+
+/// The only environment variable of the program
+const SAFE_PATH: &str =
+    "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Lowest uid and gid of the accounts, like UID_MIN and GID_MIN of
+/// login.defs
+const MIN_ID: u32 = 1000;
+
+/// Account written by the program, any other field is rejected
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Output {
+    name: Option<String>,
+    username: String,
+    uid: Option<u32>,
+    group: Option<String>,
+    gid: Option<u32>,
+    home: Option<String>,
+    shell: Option<String>,
+}
+
+impl MappingCommand {
+    /// Run the program for a RADIUS user, none if it has no mapping for the
+    /// user. The reason is returned when the program fails.
+    pub(crate) fn run(
+        &self,
+        user: &radius::User,
+    ) -> Result<Option<UserMapping>, String> {
+        let input = serde_json::to_vec(user).map_err(|err| err.to_string())?;
+        let timeout =
+            Duration::from_secs(self.timeout.unwrap_or(DEFAULT_TIMEOUT));
+        let deadline = Instant::now() + timeout;
+
+        let mut child = Command::new(&self.path)
+            .args(self.args.iter().flatten())
+            .env_clear()
+            .env("PATH", SAFE_PATH)
+            .current_dir("/")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("cannot start it: {}", err))?;
+
+        let stdout = read_all(child.stdout.take());
+        let stderr = read_all(child.stderr.take());
+
+        // Written in a thread too, the program may exit without reading its
+        // input
+        if let Some(mut stdin) = child.stdin.take() {
+            std::thread::spawn(move || {
+                if let Err(err) = stdin.write_all(&input) {
+                    log::debug!("Cannot write to mapping.command: {}", err);
+                }
+            });
+        }
+
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!(
+                        "it did not exit within {} seconds",
+                        timeout.as_secs()
+                    ));
+                }
+                Err(err) => return Err(format!("cannot wait on it: {}", err)),
+            }
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let stdout = stdout.recv_timeout(remaining).unwrap_or_default();
+        if let Ok(stderr) = stderr.recv_timeout(Duration::from_millis(100)) {
+            if !stderr.is_empty() {
+                log::warn!(
+                    "mapping.command wrote to stderr: {}",
+                    String::from_utf8_lossy(&stderr).trim_end()
+                );
+            }
+        }
+
+        match status.code() {
+            Some(0) => parse(&stdout).map(Some),
+            Some(1) => Ok(None),
+            Some(code) => Err(format!("it exited with status {}", code)),
+            None => Err("it was killed by a signal".to_string()),
+        }
+    }
+}
+
+/// Mapping of the account written on stdout
+fn parse(stdout: &[u8]) -> Result<UserMapping, String> {
+    let output: Output = serde_json::from_slice(stdout)
+        .map_err(|err| format!("invalid output, {}", err))?;
+
+    if !is_valid_username(&output.username) {
+        return Err(format!(
+            "invalid output, `{}` is not a valid local username",
+            output.username
+        ));
+    }
+    for path in output.home.iter().chain(output.shell.iter()) {
+        check_path(path).map_err(|why| format!("invalid output, {}", why))?;
+    }
+
+    Ok(UserMapping {
+        name: output.name,
+        username: output.username,
+        uid: output.uid,
+        group: output.group,
+        gid: output.gid,
+        home: output.home,
+        shell: output.shell,
+        attribute: None,
+        attribute_value: None,
+        when: None,
+        priority: None,
+        dynamic: None,
+        overrides: None,
+    })
+}
+
+/// Check a home or shell written by the program, before and after its
+/// templates are expanded
+pub(crate) fn check_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') || path.contains(':') {
+        return Err(format!("`{}` is not an absolute path", path));
+    }
+    if path.chars().any(|c| c.is_control()) {
+        return Err(format!("{:?} contains a control character", path));
+    }
+    if path.split('/').any(|part| part == "..") {
+        return Err(format!("`{}` contains `..`", path));
+    }
+    Ok(())
+}
+
+/// Check that an account resolved from the local files is not root or a
+/// system account, the program must not grant them
+pub(crate) fn check_ids(mapping: &UserMapping) -> Result<(), String> {
+    let ids = [("uid", mapping.uid), ("gid", mapping.gid)];
+    for (name, id) in ids.iter() {
+        match id {
+            Some(id) if *id < MIN_ID => {
+                return Err(format!(
+                    "{} {} of `{}` is below {}",
+                    name, id, mapping.username, MIN_ID
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Read a pipe to its end in a thread, so the program never blocks on a
+/// full pipe
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = channel();
+    if let Some(mut pipe) = pipe {
+        std::thread::spawn(move || {
+            let mut data = vec![];
+            let _ = pipe.read_to_end(&mut data);
+            let _ = sender.send(data);
+        });
+    }
+    receiver
+}
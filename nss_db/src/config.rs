@@ -1,11 +1,29 @@
 use crate::error::Error;
-use crate::user::User;
+use crate::user::{Account, User};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
-use common::serde::{decode_attr, encode_attr};
+use common::config::{Layers, Sources};
+use common::serde::{
+    decode_attr, decode_opt_attr, encode_attr, encode_opt_attr,
+};
+
+use crate::command;
+use crate::mapping::{
+    find_mapping, is_valid_username, Condition, Explanation, RuleTrace,
+};
+use crate::matcher::{AllowList, ValueMatch};
+use crate::passwd::Accounts;
+use crate::template::Template;
 
 const CONFIG_PATH: &str = "/etc/radius_auth_virtual.toml";
+const CONFIG_DIR: &str = "/etc/radius_auth_virtual.d";
+
+/// Arrays of drop-in fragments which are appended instead of replaced
+const APPENDED_KEYS: &[&str] = &["mapping.users"];
 
 #[derive(Deserialize, Debug)]
 pub struct Db {
@@ -14,25 +32,86 @@ pub struct Db {
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct UserMapping {
+    /// Name used to reference the mapping, like in `mapping.unmatched`
+    pub name: Option<String>,
+    /// Local account, the fields below which are not set are resolved from
+    /// /etc/passwd and /etc/group when the config is loaded
     pub username: String,
-    pub uid: u32,
-    pub group: String,
-    pub gid: u32,
-    pub home: String,
-    pub shell: String,
+    pub uid: Option<u32>,
+    pub group: Option<String>,
+    pub gid: Option<u32>,
+    pub home: Option<String>,
+    pub shell: Option<String>,
+    /// Attribute which must be returned with `attribute_value`
+    #[serde(
+        default,
+        serialize_with = "encode_opt_attr",
+        deserialize_with = "decode_opt_attr"
+    )]
+    pub attribute: Option<(u32, u8)>,
+    pub attribute_value: Option<ValueMatch>,
+    /// Condition which must match, in addition to the attribute
+    pub when: Option<Condition>,
+    /// The matching mapping with the highest priority is used, the first one
+    /// listed on a tie. Defaults to 0.
+    pub priority: Option<i32>,
+    /// Synthesize a dedicated account per RADIUS user. The username is a
+    /// template like home and shell, and the uid is allocated from
+    /// `mapping.dynamic`.
+    pub dynamic: Option<bool>,
+    /// Account properties taken from reply attributes
+    pub overrides: Option<Overrides>,
+}
+
+/// Account properties taken from reply attributes instead of the mapping.
+/// The mapping value is kept when the attribute is not returned.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub shell: Option<Override>,
+    pub home: Option<Override>,
+    /// Supplementary groups, every value of the attribute is the name of a
+    /// local group
+    pub groups: Option<Override>,
+    /// Seconds after which the sessions are hung up
+    pub session_timeout: Option<Override>,
+    /// Sessions which can be open at the same time
+    pub max_sessions: Option<Override>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Override {
     #[serde(serialize_with = "encode_attr", deserialize_with = "decode_attr")]
     pub attribute: (u32, u8),
-    pub attribute_value: Vec<u8>,
+    /// Values which are accepted, the user is denied otherwise. A regex must
+    /// match the whole value.
+    pub allow: AllowList,
 }
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct DefaultUser {
+    /// Local account, resolved like the username of a mapping
     pub username: String,
-    pub uid: u32,
-    pub group: String,
-    pub gid: u32,
-    pub home: String,
+    pub uid: Option<u32>,
+    pub group: Option<String>,
+    pub gid: Option<u32>,
+    pub home: Option<String>,
+    /// The shell wrapper, it is not resolved
     pub shell: String,
+    /// Shell started by the shell wrapper when the default user is used as
+    /// landing account
+    pub landing_shell: Option<String>,
+}
+
+/// Policy for RADIUS users which match no mapping
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Unmatched {
+    /// Deny the authentication
+    Deny,
+    /// Map the user to the default user
+    DefaultUser,
+    /// Map the user to the mapping with this name, whatever its conditions
+    Mapping(String),
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +119,65 @@ pub struct Mapping {
     pub db: Db,
     pub users: Vec<UserMapping>,
     pub default_user: DefaultUser,
+    /// Defaults to deny
+    pub unmatched: Option<Unmatched>,
+    /// UIDs of the dynamic accounts
+    pub dynamic: Option<Dynamic>,
+    /// Program choosing the mapping, before the mapping rules
+    pub command: Option<MappingCommand>,
+}
+
+/// External program run with the RADIUS user as JSON on stdin, which writes
+/// the local account as JSON on stdout.
+///
+/// Exit status 0 maps the user to the account, 1 uses the mapping rules.
+/// Any other status, a timeout, an invalid output or a uid or gid below 1000
+/// denies the user. The account has no overrides.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MappingCommand {
+    /// Absolute path of the program, it must be owned by root
+    pub path: PathBuf,
+    pub args: Option<Vec<String>>,
+    /// Seconds after which the program is killed, defaults to 5
+    pub timeout: Option<u64>,
+}
+
+/// Allocation of the uids of dynamic accounts
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UidAllocation {
+    /// Lowest free uid of the range
+    Sequential,
+    /// Uid derived from a hash of the username, the next free one on a
+    /// collision
+    Hash,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Dynamic {
+    /// First and last uid, inclusive. Uids of local users are skipped.
+    pub uid_range: (u32, u32),
+    /// Defaults to sequential
+    pub allocation: Option<UidAllocation>,
+}
+
+/// Mapping chosen for a RADIUS user, none if the user is denied
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decision {
+    pub mapping: Option<UserMapping>,
+    /// Groups and session limits of the mapped user, empty if denied
+    pub account: Account,
+    pub reason: String,
+}
+
+/// Action taken when a config file or directory is insecure
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionCheck {
+    /// Refuse to load the config
+    Enforce,
+    /// Log a warning
+    Warn,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,42 +185,908 @@ pub struct Config {
     pub radius: radius::Config,
     pub mapping: Mapping,
     pub debug: Option<bool>,
+    /// Reject unknown fields instead of logging them
+    pub strict: Option<bool>,
+    /// Check of the config files ownership and permissions, only read from
+    /// the main file
+    pub permission_check: Option<PermissionCheck>,
+    /// Files the config was read from
+    #[serde(skip)]
+    pub sources: Sources,
+    /// Local users and groups, read when the config is loaded
+    #[serde(skip)]
+    pub accounts: Accounts,
 }
 
 impl Config {
+    /// Read the system config file and its drop-in directory, the files
+    /// ownership and permissions are checked.
     pub fn system() -> Result<Config, Error> {
-        Config::read_file(CONFIG_PATH)
+        let config = Config::read_with_dir(CONFIG_PATH, CONFIG_DIR)?;
+        config.check_permissions()?;
+        config.validate_mapping()?;
+        Ok(config)
     }
 
     pub fn system_path() -> &'static str {
         CONFIG_PATH
     }
 
+    pub fn system_dir() -> &'static str {
+        CONFIG_DIR
+    }
+
+    /// Read a config file, unknown fields are logged as warnings unless
+    /// strict is set in the file.
     pub fn read_file<S: Into<PathBuf>>(path: S) -> Result<Config, Error> {
-        let config = std::fs::read_to_string(path.into())?;
-        let config = toml::from_str::<Config>(&config)?;
+        Config::load(vec![path.into()], false)
+    }
+
+    /// Read a config file, unknown fields are rejected
+    pub fn read_file_strict<S: Into<PathBuf>>(
+        path: S,
+    ) -> Result<Config, Error> {
+        Config::load(vec![path.into()], true)
+    }
+
+    /// Read a config file followed by the `*.toml` fragments of a drop-in
+    /// directory, in lexical order.
+    ///
+    /// Fragments override the settings of the previous files, except
+    /// `mapping.users` entries which are appended. A missing directory is
+    /// ignored.
+    pub fn read_with_dir<S: Into<PathBuf>, D: AsRef<Path>>(
+        path: S,
+        dir: D,
+    ) -> Result<Config, Error> {
+        Config::load(with_fragments(path.into(), dir.as_ref())?, false)
+    }
+
+    /// Same as `read_with_dir`, unknown fields are rejected
+    pub fn read_with_dir_strict<S: Into<PathBuf>, D: AsRef<Path>>(
+        path: S,
+        dir: D,
+    ) -> Result<Config, Error> {
+        Config::load(with_fragments(path.into(), dir.as_ref())?, true)
+    }
+
+    fn load(paths: Vec<PathBuf>, strict: bool) -> Result<Config, Error> {
+        let texts = paths
+            .iter()
+            .map(|path| {
+                std::fs::read_to_string(path).map(Zeroizing::new).map_err(
+                    |err| Error::ConfigFile(path.clone(), Box::new(err.into())),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut layers = Layers::new(APPENDED_KEYS);
+        for (path, text) in paths.iter().zip(texts.iter()) {
+            layers.add(path, text).map_err(|err| {
+                Error::ConfigFile(path.clone(), Box::new(err.into()))
+            })?;
+        }
+
+        let (mut config, unknown, sources) = layers
+            .deserialize::<Config>()
+            .map_err(|err| match err.path {
+                Some(path) => {
+                    Error::ConfigFile(path, Box::new(err.error.into()))
+                }
+                None => err.error.into(),
+            })?;
+        config.sources = sources;
+
+        if (strict || config.strict.unwrap_or(false)) && !unknown.is_empty() {
+            return Err(Error::UnknownFields(unknown));
+        }
+
+        for field in unknown.iter() {
+            log::warn!("Ignoring {}", field);
+        }
+
+        let accounts = Accounts::system()?;
+        config.resolve_accounts(&accounts)?;
+        config.accounts = accounts;
+
         Ok(config)
     }
 
-    pub fn map_user(&self, radius: &radius::User) -> Option<User> {
-        for user in self.mapping.users.iter() {
-            for attr in radius.attributes.iter() {
-                if attr.vendor == user.attribute.0
-                    && attr.subtype == user.attribute.1
-                    && attr.data == user.attribute_value
+    /// Fill the account fields of the mappings and the default user which
+    /// are not set from the local account with the same username.
+    ///
+    /// Fields which are set must match the local account. A local account
+    /// which does not exist is only an error when a field is missing.
+    pub fn resolve_accounts(
+        &mut self,
+        accounts: &Accounts,
+    ) -> Result<(), Error> {
+        let mut errors = vec![];
+
+        let default = &mut self.mapping.default_user;
+        let mut fields = Fields {
+            username: &default.username,
+            uid: &mut default.uid,
+            group: &mut default.group,
+            gid: &mut default.gid,
+            home: &mut default.home,
+            shell: None,
+        };
+        let default_errors = fields.resolve(accounts);
+
+        let mut user_errors = vec![];
+        for (i, user) in self.mapping.users.iter_mut().enumerate() {
+            let errors = if user.is_dynamic() {
+                resolve_group(accounts, user.gid, &mut user.group)
+            } else {
+                let mut fields = Fields {
+                    username: &user.username,
+                    uid: &mut user.uid,
+                    group: &mut user.group,
+                    gid: &mut user.gid,
+                    home: &mut user.home,
+                    shell: Some(&mut user.shell),
+                };
+                fields.resolve(accounts)
+            };
+            let key = format!("mapping.users[{}]", i);
+            user_errors.extend(errors.into_iter().map(|e| (key.clone(), e)));
+        }
+
+        for err in default_errors {
+            errors.push(self.located("mapping.default_user", err));
+        }
+        for (key, err) in user_errors {
+            errors.push(self.located(&key, err));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(errors))
+        }
+    }
+
+    /// Check the whole configuration, every problem found is reported in
+    /// `Error::InvalidConfig`.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut errors = vec![];
+
+        match self.radius.validate() {
+            Ok(()) => {}
+            Err(radius::Error::InvalidConfig(errs)) => {
+                errors.extend(errs.into_iter().map(Error::Radius))
+            }
+            Err(err) => errors.push(Error::Radius(err)),
+        }
+
+        errors.extend(self.mapping_errors());
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(errors))
+        }
+    }
+
+    /// Check the mapping section, like `validate` without the RADIUS
+    /// servers. The modules load the configuration with it: resolving the
+    /// servers and reading the shared secret is left to the client.
+    pub fn validate_mapping(&self) -> Result<(), Error> {
+        let errors = self.mapping_errors();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(errors))
+        }
+    }
+
+    fn mapping_errors(&self) -> Vec<Error> {
+        let mut errors = vec![];
+
+        let attributes = self.radius.attributes.as_deref().unwrap_or(&[]);
+
+        for (i, user) in self.mapping.users.iter().enumerate() {
+            let key = format!("mapping.users[{}]", i);
+
+            if !user.has_condition() && !self.is_fallback(user) {
+                errors.push(self.located(
+                    &key,
+                    Error::MappingWithoutCondition(user.username.clone()),
+                ));
+            }
+
+            if user.attribute.is_some() != user.attribute_value.is_some() {
+                errors.push(self.located(
+                    &key,
+                    Error::IncompleteMappingAttribute(user.username.clone()),
+                ));
+            }
+
+            if let Some(when) = &user.when {
+                if when.has_value_without_attribute() {
+                    errors.push(self.located(
+                        &key,
+                        Error::ValueWithoutAttribute(user.username.clone()),
+                    ));
+                }
+            }
+
+            if user.is_dynamic() {
+                match Template::parse(&user.username) {
+                    Ok(template) if template.uses_local_user() => {
+                        errors.push(self.located(
+                            &key,
+                            Error::InvalidTemplate(
+                                user.username.clone(),
+                                "`%l` cannot be used in the username",
+                            ),
+                        ))
+                    }
+                    Ok(_) => {}
+                    Err(err) => errors.push(self.located(&key, err)),
+                }
+            }
+            for template in user.home.iter().chain(user.shell.iter()) {
+                if let Err(err) = Template::parse(template) {
+                    errors.push(self.located(&key, err));
+                }
+            }
+
+            if user.is_dynamic() {
+                if user.uid.is_some() {
+                    errors.push(self.located(
+                        &key,
+                        Error::InvalidDynamicMapping(
+                            user.username.clone(),
+                            "the uid is allocated and cannot be set",
+                        ),
+                    ));
+                }
+                if user.gid.is_none()
+                    || user.home.is_none()
+                    || user.shell.is_none()
                 {
-                    return Some(User {
-                        radius: radius.clone(),
-                        mapping: user.clone(),
-                    });
+                    errors.push(self.located(
+                        &key,
+                        Error::InvalidDynamicMapping(
+                            user.username.clone(),
+                            "gid, home and shell must be set",
+                        ),
+                    ));
+                }
+            }
+
+            if let Some(overrides) = &user.overrides {
+                for field in overrides.unrestricted() {
+                    errors.push(self.located(
+                        &key,
+                        Error::UnrestrictedOverride(
+                            user.username.clone(),
+                            field,
+                        ),
+                    ));
+                }
+            }
+
+            for attribute in user.attributes() {
+                if !attributes.contains(&attribute) {
+                    errors.push(self.located(
+                        &key,
+                        Error::UnrequestedAttribute(
+                            user.username.clone(),
+                            attribute,
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(command) = &self.mapping.command {
+            let invalid = |reason| {
+                Error::InvalidMappingCommand(command.path.clone(), reason)
+            };
+            if !command.path.is_absolute() {
+                errors.push(invalid("the path must be absolute"));
+            }
+            if command.timeout == Some(0) {
+                errors.push(invalid("the timeout must be at least 1 second"));
+            }
+        }
+
+        let mut names: Vec<&String> = vec![];
+        for user in self.mapping.users.iter() {
+            if let Some(name) = &user.name {
+                if names.contains(&name) {
+                    errors.push(Error::DuplicateMappingName(name.clone()));
+                }
+                names.push(name);
+            }
+        }
+
+        match &self.mapping.unmatched {
+            Some(Unmatched::Mapping(name)) if !names.contains(&name) => {
+                errors.push(Error::UnknownFallbackMapping(name.clone()));
+            }
+            Some(Unmatched::DefaultUser)
+                if self.mapping.default_user.landing_shell.is_none() =>
+            {
+                errors.push(Error::NoLandingShell);
+            }
+            _ => {}
+        }
+
+        let dynamic = self.mapping.users.iter().any(|m| m.is_dynamic());
+        match &self.mapping.dynamic {
+            Some(Dynamic {
+                uid_range: (first, last),
+                ..
+            }) if *first == 0 || first > last => {
+                errors.push(self.located(
+                    "mapping.dynamic.uid_range",
+                    Error::InvalidUidRange(*first, *last),
+                ));
+            }
+            None if dynamic => errors.push(Error::NoUidRange),
+            _ => {}
+        }
+
+        let db_dir = self.mapping.db.path.parent().unwrap_or(Path::new("/"));
+        if !db_dir.as_os_str().is_empty() && !db_dir.is_dir() {
+            errors.push(self.located(
+                "mapping.db.path",
+                Error::InvalidDbPath(self.mapping.db.path.clone()),
+            ));
+        }
+
+        errors
+    }
+
+    /// Check that the config files, the mapping command and their
+    /// directories are owned by root and not writable by group or others.
+    ///
+    /// With `permission_check = "warn"` in the main file, problems are
+    /// logged instead of returned.
+    pub fn check_permissions(&self) -> Result<(), Error> {
+        let main = self.sources.files().first().map(|path| path.as_path());
+        let check = if self.sources.file_of("permission_check") == main {
+            self.permission_check.unwrap_or(PermissionCheck::Enforce)
+        } else {
+            PermissionCheck::Enforce
+        };
+
+        let mut paths: Vec<&Path> = vec![];
+        for file in self.sources.files() {
+            paths.push(file);
+            if let Some(dir) = file.parent().filter(|dir| !paths.contains(dir))
+            {
+                paths.push(dir);
+            }
+        }
+
+        if let Some(command) = &self.mapping.command {
+            paths.push(&command.path);
+            if let Some(dir) = command.path.parent() {
+                paths.push(dir);
+            }
+        }
+
+        for path in paths {
+            match (check_path(path), check) {
+                (Ok(()), _) => {}
+                (Err(err), PermissionCheck::Enforce) => return Err(err),
+                (Err(err), PermissionCheck::Warn) => log::warn!("{}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_fallback(&self, mapping: &UserMapping) -> bool {
+        match (&self.mapping.unmatched, &mapping.name) {
+            (Some(Unmatched::Mapping(fallback)), Some(name)) => {
+                fallback == name
+            }
+            _ => false,
+        }
+    }
+
+    /// Attach the file which set a key to an error, when the config was read
+    /// from several files
+    fn located(&self, key: &str, err: Error) -> Error {
+        match self.sources.file_of(key) {
+            Some(path) if self.sources.files().len() > 1 => {
+                Error::ConfigFile(path.to_path_buf(), Box::new(err))
+            }
+            _ => err,
+        }
+    }
+
+    /// Merge the attributes of a CoA-Request into a stored RADIUS user, they
+    /// replace the stored attributes of the same type. Attributes which are
+    /// not listed in `radius.attributes` are ignored.
+    pub fn merge_attributes(
+        &self,
+        radius: &radius::User,
+        attributes: &[radius::Attribute],
+    ) -> radius::User {
+        let listed = self.radius.attributes.as_deref().unwrap_or(&[]);
+        let changes: Vec<_> = attributes
+            .iter()
+            .filter(|attr| listed.contains(&(attr.vendor, attr.subtype)))
+            .collect();
+
+        let mut merged = radius.clone();
+        merged.attributes.retain(|attr| {
+            !changes.iter().any(|change| {
+                change.vendor == attr.vendor && change.subtype == attr.subtype
+            })
+        });
+        merged.attributes.extend(changes.into_iter().cloned());
+        merged
+    }
+
+    /// Map a RADIUS user to the matching mapping with the highest priority,
+    /// or according to the unmatched policy. The decision is logged.
+    pub fn map_user(&self, radius: &radius::User) -> Option<User> {
+        let decision = self.decide(radius);
+
+        match decision.mapping {
+            Some(mapping) => {
+                log::info!(
+                    "Mapping RADIUS user {} to {}: {}",
+                    radius.username,
+                    mapping.username,
+                    decision.reason
+                );
+                Some(User {
+                    radius: radius.clone(),
+                    mapping,
+                    account: decision.account,
+                })
+            }
+            None => {
+                log::info!(
+                    "Denying RADIUS user {}: {}",
+                    radius.username,
+                    decision.reason
+                );
+                None
+            }
+        }
+    }
+
+    /// Choose the mapping of a RADIUS user, without logging
+    pub fn decide(&self, radius: &radius::User) -> Decision {
+        let mut prefix = String::new();
+        if let Some(command) = &self.mapping.command {
+            let result = command
+                .run(radius)
+                .map_err(|why| format!("mapping.command failed, {}", why));
+            match result.and_then(|m| self.command_account(m, radius)) {
+                Ok(Some(mapping)) => {
+                    return Decision {
+                        mapping: Some(mapping),
+                        account: Account::default(),
+                        reason: "mapped by mapping.command".to_string(),
+                    }
+                }
+                Ok(None) => prefix = "mapping.command has no mapping, ".into(),
+                Err(reason) => {
+                    return Decision {
+                        mapping: None,
+                        account: Account::default(),
+                        reason,
+                    }
+                }
+            }
+        }
+
+        let users = &self.mapping.users;
+
+        let (mapping, reason) = match find_mapping(users, radius) {
+            Some((i, mapping)) => (
+                Some(mapping.clone()),
+                format!(
+                    "matched {} with priority {}",
+                    mapping.label(i),
+                    mapping.priority.unwrap_or(0)
+                ),
+            ),
+            None => {
+                let (mapping, reason) = self.unmatched();
+                (mapping, format!("no mapping matched, {}", reason))
+            }
+        };
+
+        let reason = prefix + &reason;
+
+        match mapping.map(|mapping| self.account(mapping, radius)) {
+            Some(Ok((mapping, account))) => Decision {
+                mapping: Some(mapping),
+                account,
+                reason,
+            },
+            Some(Err(why)) => Decision {
+                mapping: None,
+                account: Account::default(),
+                reason: format!("{}, but {}", reason, why),
+            },
+            None => Decision {
+                mapping: None,
+                account: Account::default(),
+                reason,
+            },
+        }
+    }
+
+    fn unmatched(&self) -> (Option<UserMapping>, String) {
+        let users = &self.mapping.users;
+
+        match &self.mapping.unmatched {
+            None | Some(Unmatched::Deny) => {
+                (None, "unmatched users are denied".to_string())
+            }
+            Some(Unmatched::DefaultUser) => {
+                let default = &self.mapping.default_user;
+                match default.to_mapping() {
+                    Some(mapping) => (
+                        Some(mapping),
+                        format!("using default user `{}`", default.username),
+                    ),
+                    None => {
+                        (None, "default user has no landing_shell".to_string())
+                    }
+                }
+            }
+            Some(Unmatched::Mapping(name)) => {
+                match users.iter().find(|m| m.name.as_ref() == Some(name)) {
+                    Some(mapping) => (
+                        Some(mapping.clone()),
+                        format!("using fallback mapping `{}`", name),
+                    ),
+                    None => (
+                        None,
+                        format!("fallback mapping `{}` does not exist", name),
+                    ),
                 }
             }
         }
+    }
+
+    /// Account of a RADIUS user with a mapping, the username of dynamic
+    /// mappings and the home and shell templates are expanded, then the
+    /// overrides are applied. The reason is returned when the account cannot
+    /// be created.
+    fn account(
+        &self,
+        mut mapping: UserMapping,
+        radius: &radius::User,
+    ) -> Result<(UserMapping, Account), String> {
+        let expand =
+            |template: &str, local| expand_template(template, radius, local);
+
+        if mapping.is_dynamic() {
+            let username = expand(&mapping.username, None)?;
+            if !is_valid_username(&username) {
+                return Err(format!(
+                    "`{}` is not a valid local username",
+                    username
+                ));
+            }
+            if self.accounts.user(&username).is_some() {
+                return Err(format!(
+                    "local user `{}` already exists",
+                    username
+                ));
+            }
+            mapping.username = username;
+        }
+
+        let local = Some(mapping.username.as_str());
+        let home = match &mapping.home {
+            Some(home) => Some(expand(home, local)?),
+            None => None,
+        };
+        let shell = match &mapping.shell {
+            Some(shell) => Some(expand(shell, local)?),
+            None => None,
+        };
+
+        mapping.home = home;
+        mapping.shell = shell;
+
+        let account = match mapping.overrides.clone() {
+            Some(overrides) => {
+                overrides.apply(&mut mapping, radius, &self.accounts)?
+            }
+            None => Account::default(),
+        };
+        Ok((mapping, account))
+    }
+
+    /// Resolve the account returned by the mapping command like the accounts
+    /// of the mapping rules, the home and shell templates are expanded and
+    /// system accounts are rejected. Overrides, groups and session limits
+    /// only apply to the mapping rules.
+    fn command_account(
+        &self,
+        mapping: Option<UserMapping>,
+        radius: &radius::User,
+    ) -> Result<Option<UserMapping>, String> {
+        let mut mapping = match mapping {
+            Some(mapping) => mapping,
+            None => return Ok(None),
+        };
+
+        // Values completed from the local account are not templates
+        let home_template = matches!(&mapping.home, Some(h) if is_template(h));
+        let shell_template =
+            matches!(&mapping.shell, Some(s) if is_template(s));
+
+        let mut fields = Fields {
+            username: &mapping.username,
+            uid: &mut mapping.uid,
+            group: &mut mapping.group,
+            gid: &mut mapping.gid,
+            home: &mut mapping.home,
+            shell: Some(&mut mapping.shell),
+        };
+        if let Some(err) = fields.resolve(&self.accounts).first() {
+            return Err(format!("mapping.command returned {}", err));
+        }
+
+        let local = mapping.username.clone();
+        let expand = |path: &str| {
+            expand_template(path, radius, Some(&local))
+                .and_then(|path| command::check_path(&path).map(|()| path))
+                .map_err(|why| format!("mapping.command returned {}", why))
+        };
+        if home_template {
+            mapping.home = mapping.home.as_deref().map(expand).transpose()?;
+        }
+        if shell_template {
+            mapping.shell = mapping.shell.as_deref().map(expand).transpose()?;
+        }
 
-        None
+        command::check_ids(&mapping)
+            .map_err(|why| format!("mapping.command returned {}", why))?;
+        Ok(Some(mapping))
+    }
+
+    /// Evaluate every mapping rule for a RADIUS user, to explain the
+    /// decision
+    pub fn explain_mapping(&self, radius: &radius::User) -> Explanation {
+        let rules = self
+            .mapping
+            .users
+            .iter()
+            .enumerate()
+            .map(|(i, mapping)| RuleTrace {
+                label: mapping.label(i),
+                username: mapping.username.clone(),
+                priority: mapping.priority.unwrap_or(0),
+                check: mapping.check(radius),
+            })
+            .collect();
+
+        Explanation {
+            rules,
+            decision: self.decide(radius),
+        }
     }
 
     pub fn debug(&self) -> bool {
         self.debug.unwrap_or(false)
     }
 }
+
+impl DefaultUser {
+    /// Mapping used when the default user is the landing account, none
+    /// without landing shell
+    fn to_mapping(&self) -> Option<UserMapping> {
+        Some(UserMapping {
+            name: None,
+            username: self.username.clone(),
+            uid: self.uid,
+            group: self.group.clone(),
+            gid: self.gid,
+            home: self.home.clone(),
+            shell: Some(self.landing_shell.clone()?),
+            attribute: None,
+            attribute_value: None,
+            when: None,
+            priority: None,
+            dynamic: None,
+            overrides: None,
+        })
+    }
+}
+
+/// Expand a template of a mapping, the reason is returned when it is invalid
+/// or a value cannot be used
+fn expand_template(
+    template: &str,
+    radius: &radius::User,
+    local: Option<&str>,
+) -> Result<String, String> {
+    Template::parse(template)
+        .map_err(|err| err.to_string())?
+        .expand(radius, local)
+}
+
+fn is_template(value: &str) -> bool {
+    value.contains('%')
+}
+
+/// Group of a dynamic mapping, which is not a local user
+fn resolve_group(
+    accounts: &Accounts,
+    gid: Option<u32>,
+    group: &mut Option<String>,
+) -> Vec<Error> {
+    let local = match gid.and_then(|gid| accounts.group(gid)) {
+        Some(local) => local,
+        None => return vec![],
+    };
+
+    match group {
+        Some(name) if name != &local.name => vec![Error::GroupMismatch(
+            local.gid,
+            name.clone(),
+            local.name.clone(),
+        )],
+        Some(_) => vec![],
+        None => {
+            *group = Some(local.name.clone());
+            vec![]
+        }
+    }
+}
+
+/// Account fields of a mapping or of the default user
+struct Fields<'a> {
+    username: &'a str,
+    uid: &'a mut Option<u32>,
+    group: &'a mut Option<String>,
+    gid: &'a mut Option<u32>,
+    home: &'a mut Option<String>,
+    /// Not resolved for the default user
+    shell: Option<&'a mut Option<String>>,
+}
+
+impl<'a> Fields<'a> {
+    fn resolve(&mut self, accounts: &Accounts) -> Vec<Error> {
+        let local = match accounts.user(self.username) {
+            Some(local) => local,
+            None if self.is_complete() => return vec![],
+            None => {
+                return vec![Error::UnknownLocalUser(self.username.to_string())]
+            }
+        };
+
+        let username = self.username;
+        let mismatch = |field, set: String, local: String| {
+            if set == local {
+                None
+            } else {
+                Some(Error::AccountMismatch(
+                    username.to_string(),
+                    field,
+                    set,
+                    local,
+                ))
+            }
+        };
+
+        let mut errors = vec![];
+
+        match self.uid {
+            Some(uid) => errors.extend(mismatch(
+                "uid",
+                uid.to_string(),
+                local.uid.to_string(),
+            )),
+            None => *self.uid = Some(local.uid),
+        }
+        match self.gid {
+            Some(gid) => errors.extend(mismatch(
+                "gid",
+                gid.to_string(),
+                local.gid.to_string(),
+            )),
+            None => *self.gid = Some(local.gid),
+        }
+        // Templates are expanded for each RADIUS user, they cannot be
+        // compared with the local account
+        match self.home {
+            Some(home) if is_template(home) => {}
+            Some(home) => errors.extend(mismatch(
+                "home",
+                home.clone(),
+                local.home.clone(),
+            )),
+            None => *self.home = Some(local.home.clone()),
+        }
+        if let Some(shell) = &mut self.shell {
+            match shell {
+                Some(shell) if is_template(shell) => {}
+                Some(shell) => errors.extend(mismatch(
+                    "shell",
+                    shell.clone(),
+                    local.shell.clone(),
+                )),
+                None => **shell = Some(local.shell.clone()),
+            }
+        }
+
+        match (accounts.group(local.gid), &self.group) {
+            (Some(group), Some(name)) => errors.extend(mismatch(
+                "group",
+                name.clone(),
+                group.name.clone(),
+            )),
+            (Some(group), None) => *self.group = Some(group.name.clone()),
+            (None, Some(_)) => {}
+            (None, None) => errors.push(Error::UnknownLocalGroup(local.gid)),
+        }
+
+        errors
+    }
+
+    fn is_complete(&self) -> bool {
+        let shell = match &self.shell {
+            Some(shell) => shell.is_some(),
+            None => true,
+        };
+        self.uid.is_some()
+            && self.group.is_some()
+            && self.gid.is_some()
+            && self.home.is_some()
+            && shell
+    }
+}
+
+/// Check that a file or directory is owned by root and not writable by group
+/// or others
+fn check_path(path: &Path) -> Result<(), Error> {
+    let insecure = |reason| Error::InsecurePermissions(path.into(), reason);
+    let dir = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+
+    let metadata = std::fs::metadata(dir)?;
+
+    if metadata.uid() != 0 {
+        return Err(insecure("not owned by root"));
+    }
+
+    if metadata.mode() & 0o022 != 0 {
+        return Err(insecure("writable by group or others"));
+    }
+
+    Ok(())
+}
+
+/// A config file followed by the fragments of a drop-in directory
+fn with_fragments(path: PathBuf, dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut fragments = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(err) => return Err(err.into()),
+    };
+
+    fragments.retain(|path| {
+        path.extension() == Some(OsStr::new("toml")) && path.is_file()
+    });
+    fragments.sort();
+
+    let mut paths = vec![path];
+    paths.append(&mut fragments);
+    Ok(paths)
+}
@@ -1,15 +1,23 @@
-use crate::config::Config;
+use crate::config::{Config, Dynamic, UidAllocation};
 use crate::error::*;
+use crate::session::Session;
 use crate::user::User;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlite::{Connection, Value};
+use std::collections::HashSet;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-const VERSION: &str = "1";
+const VERSION: &str = "3";
+
+/// Milliseconds to wait on a database locked by another process
+const BUSY_TIMEOUT: usize = 5000;
 
 pub struct Db {
     conn: Connection,
+    dynamic: Option<Dynamic>,
+    /// Uids of the local users, never allocated
+    local_uids: HashSet<u32>,
 }
 
 impl Db {
@@ -21,10 +29,14 @@ impl Db {
             .set_full_mutex()
             .set_read_write();
 
-        let db = Db {
+        let mut db = Db {
             conn: Connection::open_with_flags(path, flags)?,
+            dynamic: config.mapping.dynamic.clone(),
+            local_uids: config.accounts.uids().collect(),
         };
 
+        db.conn.set_busy_timeout(BUSY_TIMEOUT)?;
+
         if cfg!(unix) {
             use std::fs::Permissions;
             use std::os::unix::fs::PermissionsExt;
@@ -32,21 +44,32 @@ impl Db {
             std::fs::set_permissions(path, perms)?;
         }
 
-        let mut version_ok = true;
+        let mut version = String::new();
 
         db.conn.iterate("PRAGMA user_version", |pairs| {
             for &(_column, value) in pairs.iter() {
-                let value = value.unwrap();
-                if value != "0" && value != VERSION {
-                    version_ok = false;
-                }
+                version = value.unwrap().to_string();
             }
             true
         })?;
 
-        if version_ok == false {
+        // Version 1 has no uid column, and version 2 no local_username
+        // column, which is set on the next login of the users
+        if version != "0"
+            && version != "1"
+            && version != "2"
+            && version != VERSION
+        {
             return Err(Error::IncompatibleDbVersion);
         }
+        if version == "1" {
+            db.conn
+                .execute("ALTER TABLE users ADD COLUMN uid INTEGER")?;
+        }
+        if version == "1" || version == "2" {
+            db.conn
+                .execute("ALTER TABLE users ADD COLUMN local_username TEXT")?;
+        }
         db.conn
             .execute(format!("PRAGMA user_version = {}", VERSION))?;
 
@@ -57,45 +80,178 @@ impl Db {
               username TEXT,
               last_login INTEGER,
               serialized_user BLOB,
-              cookie TEXT
+              cookie TEXT,
+              uid INTEGER,
+              local_username TEXT
             );
             CREATE UNIQUE INDEX IF NOT EXISTS users_unique
             ON users(username);
+            CREATE UNIQUE INDEX IF NOT EXISTS users_uid
+            ON users(uid);
+            CREATE INDEX IF NOT EXISTS users_local_username
+            ON users(local_username);
+            CREATE TABLE IF NOT EXISTS sessions (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              session_id TEXT,
+              username TEXT,
+              pid INTEGER,
+              started INTEGER
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS sessions_unique
+            ON sessions(session_id);
             ",
         )?;
         Ok(db)
     }
 
+    /// Store a user and return its new cookie.
+    ///
+    /// A uid is allocated for users with a dynamic mapping, it is kept
+    /// across logins.
     pub fn store_user(&mut self, user: &User) -> Result<String, Error> {
         let cookie: String =
             thread_rng().sample_iter(&Alphanumeric).take(32).collect();
 
+        self.transaction(|db| {
+            let (user, uid) = db.with_uid(user)?;
+
+            let mut stm = db
+                .conn
+                .prepare(
+                    "INSERT INTO users
+                    (username, last_login, serialized_user, cookie, uid,
+                    local_username)
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    ON CONFLICT (username)
+                    DO UPDATE SET
+                    last_login=excluded.last_login,
+                    serialized_user=excluded.serialized_user,
+                    cookie=excluded.cookie,
+                    uid=excluded.uid,
+                    local_username=excluded.local_username
+                    ",
+                )?
+                .cursor();
+
+            let buf = serde_cbor::to_vec(&user)?;
+
+            stm.bind(&[
+                Value::String(user.radius.username.clone()),
+                Value::Integer(now()),
+                Value::Binary(buf),
+                Value::String(cookie.clone()),
+                uid,
+                Value::String(user.mapping.username.clone()),
+            ])?;
+
+            stm.next()?;
+            Ok(())
+        })?;
+
+        Ok(cookie)
+    }
+
+    /// Replace the mapping and account of a stored user, its cookie and
+    /// dynamic uid are kept.
+    pub fn update_user(&mut self, user: &User) -> Result<(), Error> {
+        self.transaction(|db| {
+            let (user, uid) = db.with_uid(user)?;
+
+            let mut stm = db
+                .conn
+                .prepare(
+                    "UPDATE users
+                    SET serialized_user = ?, uid = ?, local_username = ?
+                    WHERE username = ?",
+                )?
+                .cursor();
+
+            let buf = serde_cbor::to_vec(&user)?;
+
+            stm.bind(&[
+                Value::Binary(buf),
+                uid,
+                Value::String(user.mapping.username.clone()),
+                Value::String(user.radius.username.clone()),
+            ])?;
+
+            stm.next()?;
+            Ok(())
+        })
+    }
+
+    /// Copy of the user with the uid of its dynamic account, and the value
+    /// of the uid column
+    fn with_uid(&self, user: &User) -> Result<(User, Value), Error> {
+        let mut user = user.clone();
+        let uid = if user.mapping.is_dynamic() {
+            let uid = self.allocate_uid(&user.radius.username)?;
+            user.mapping.uid = Some(uid);
+            Value::Integer(uid as i64)
+        } else {
+            Value::Null
+        };
+        Ok((user, uid))
+    }
+
+    /// Run the queries in a transaction which holds the write lock from the
+    /// start, so concurrent logins cannot allocate the same uid
+    fn transaction<T, F>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        self.conn.execute("BEGIN IMMEDIATE")?;
+        match f(self) {
+            Ok(value) => {
+                self.conn.execute("COMMIT")?;
+                Ok(value)
+            }
+            Err(err) => {
+                let _ = self.conn.execute("ROLLBACK");
+                Err(err)
+            }
+        }
+    }
+
+    /// Uid of a dynamic account, the one already stored for the user or the
+    /// first free one of the range
+    fn allocate_uid(&self, username: &str) -> Result<u32, Error> {
+        let dynamic = self.dynamic.as_ref().ok_or(Error::NoUidRange)?;
+        let (first, last) = dynamic.uid_range;
+
         let mut stm = self
             .conn
-            .prepare(
-                "INSERT INTO users
-                (username, last_login, serialized_user, cookie)
-                VALUES (?, ?, ?, ?)
-                ON CONFLICT (username)
-                DO UPDATE SET
-                last_login=excluded.last_login,
-                serialized_user=excluded.serialized_user,
-                cookie=excluded.cookie
-                ",
-            )?
+            .prepare("SELECT uid FROM users WHERE username = ?")?
             .cursor();
+        stm.bind(&[Value::String(username.to_string())])?;
+        if let Some(row) = stm.next()? {
+            if let Some(uid) = row[0].as_integer() {
+                return Ok(uid as u32);
+            }
+        }
 
-        let buf = serde_cbor::to_vec(&user)?;
+        let mut stm = self
+            .conn
+            .prepare("SELECT uid FROM users WHERE uid BETWEEN ? AND ?")?
+            .cursor();
+        stm.bind(&[Value::Integer(first as i64), Value::Integer(last as i64)])?;
+        let mut used = self.local_uids.clone();
+        while let Some(row) = stm.next()? {
+            if let Some(uid) = row[0].as_integer() {
+                used.insert(uid as u32);
+            }
+        }
 
-        stm.bind(&[
-            Value::String(user.radius.username.clone()),
-            Value::Integer(now()),
-            Value::Binary(buf),
-            Value::String(cookie.clone()),
-        ])?;
+        let size = (last - first) as u64 + 1;
+        let start = match dynamic.allocation {
+            Some(UidAllocation::Hash) => fnv1a(username) as u64 % size,
+            Some(UidAllocation::Sequential) | None => 0,
+        };
 
-        stm.next()?;
-        Ok(cookie)
+        (0..size)
+            .map(|i| first + ((start + i) % size) as u32)
+            .find(|uid| !used.contains(uid))
+            .ok_or(Error::UidRangeExhausted)
     }
 
     pub fn get_user<S: Into<String>>(
@@ -112,6 +268,42 @@ impl Db {
         self.run_user_query(stm)
     }
 
+    /// User of a dynamic account
+    pub fn get_user_by_uid(&self, uid: u32) -> Result<User, Error> {
+        let mut stm = self
+            .conn
+            .prepare("SELECT (serialized_user) FROM users WHERE uid = ?")?
+            .cursor();
+
+        stm.bind(&[Value::Integer(uid as i64)])?;
+
+        self.run_user_query(stm)
+    }
+
+    /// User of a dynamic account by its local username, the most recent
+    /// login when several RADIUS users share it
+    pub fn get_user_by_local_name(&self, name: &str) -> Result<User, Error> {
+        let mut stm = self
+            .conn
+            .prepare(
+                "SELECT (serialized_user) FROM users
+                WHERE local_username = ? AND uid IS NOT NULL
+                ORDER BY last_login DESC LIMIT 1",
+            )?
+            .cursor();
+
+        stm.bind(&[Value::String(name.to_string())])?;
+
+        self.run_user_query(stm)
+    }
+
+    /// User looked up by NSS, by its RADIUS username or else by the local
+    /// username of its dynamic account
+    pub fn find_user(&self, name: &str) -> Result<User, Error> {
+        self.get_user(name)
+            .or_else(|_| self.get_user_by_local_name(name))
+    }
+
     pub fn get_user_with_cookie<S: Into<String>>(
         &self,
         username: S,
@@ -130,6 +322,113 @@ impl Db {
         self.run_user_query(stm)
     }
 
+    pub fn delete_user<S: Into<String>>(
+        &mut self,
+        username: S,
+    ) -> Result<(), Error> {
+        let mut stm = self
+            .conn
+            .prepare("DELETE FROM users WHERE username = ?")?
+            .cursor();
+
+        stm.bind(&[Value::String(username.into())])?;
+
+        stm.next()?;
+        Ok(())
+    }
+
+    /// Invalidate the cookie of a user, no session can start until it logs
+    /// in again. The user is kept, with the uid of its dynamic account.
+    pub fn revoke_user(&mut self, username: &str) -> Result<(), Error> {
+        let mut stm = self
+            .conn
+            .prepare("UPDATE users SET cookie = NULL WHERE username = ?")?
+            .cursor();
+
+        stm.bind(&[Value::String(username.to_string())])?;
+
+        stm.next()?;
+        Ok(())
+    }
+
+    pub fn store_session<S: Into<String>>(
+        &mut self,
+        username: S,
+        pid: u32,
+    ) -> Result<Session, Error> {
+        let session = Session {
+            session_id: thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .collect(),
+            username: username.into(),
+            pid: pid as i64,
+            started: now(),
+        };
+
+        let mut stm = self
+            .conn
+            .prepare(
+                "INSERT INTO sessions
+                (session_id, username, pid, started)
+                VALUES (?, ?, ?, ?)
+                ",
+            )?
+            .cursor();
+
+        stm.bind(&[
+            Value::String(session.session_id.clone()),
+            Value::String(session.username.clone()),
+            Value::Integer(session.pid),
+            Value::Integer(session.started),
+        ])?;
+
+        stm.next()?;
+        Ok(session)
+    }
+
+    /// Find the sessions of a user
+    pub fn find_sessions(&self, username: &str) -> Result<Vec<Session>, Error> {
+        let mut stm = self
+            .conn
+            .prepare(
+                "SELECT session_id, username, pid, started FROM sessions
+                WHERE username = ?
+                ",
+            )?
+            .cursor();
+
+        stm.bind(&[Value::String(username.to_string())])?;
+
+        let mut sessions = vec![];
+
+        while let Some(row) = stm.next()? {
+            sessions.push(Session {
+                session_id: row[0].as_string().unwrap_or("").to_string(),
+                username: row[1].as_string().unwrap_or("").to_string(),
+                pid: row[2].as_integer().unwrap_or(0),
+                started: row[3].as_integer().unwrap_or(0),
+            });
+        }
+
+        Ok(sessions)
+    }
+
+    pub fn delete_session<S: Into<String>>(
+        &mut self,
+        session_id: S,
+    ) -> Result<(), Error> {
+        let mut stm = self
+            .conn
+            .prepare("DELETE FROM sessions WHERE session_id = ?")?
+            .cursor();
+
+        stm.bind(&[Value::String(session_id.into())])?;
+
+        stm.next()?;
+        Ok(())
+    }
+
     fn run_user_query(&self, stm: sqlite::Cursor) -> Result<User, Error> {
         let mut stm = stm;
 
@@ -143,6 +442,13 @@ impl Db {
     }
 }
 
+/// 32 bits FNV-1a, which is stable across builds unlike the std hasher
+fn fnv1a(s: &str) -> u32 {
+    s.bytes().fold(0x811c_9dc5, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
 fn now() -> i64 {
     let start = SystemTime::now();
     let now = start
@@ -1,3 +1,4 @@
+use common::config::UnknownField;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -16,4 +17,64 @@ pub enum Error {
     CborSerializeError(#[from] serde_cbor::error::Error),
     #[error("Underlying IO error: {0}")]
     IOError(#[from] std::io::Error),
+    #[error("RADIUS: {0}")]
+    Radius(radius::Error),
+    #[error("Mapping of `{0}` uses attribute {}.{} which is not listed in radius.attributes", .1.0, .1.1)]
+    UnrequestedAttribute(String, (u32, u8)),
+    #[error("Mapping of `{0}` has no condition")]
+    MappingWithoutCondition(String),
+    #[error("Mapping of `{0}` must set both attribute and attribute_value")]
+    IncompleteMappingAttribute(String),
+    #[error("Mapping of `{0}` has a condition with a value but no attribute")]
+    ValueWithoutAttribute(String),
+    #[error("Mapping name `{0}` is used more than once")]
+    DuplicateMappingName(String),
+    #[error("Fallback mapping `{0}` does not exist")]
+    UnknownFallbackMapping(String),
+    #[error("Default user has no landing_shell, it cannot be used for unmatched users")]
+    NoLandingShell,
+    #[error(
+        "Mapping of `{0}` overrides {1} with any value, set an allow-list"
+    )]
+    UnrestrictedOverride(String, &'static str),
+    #[error("Invalid mapping.command `{}`: {1}", .0.display())]
+    InvalidMappingCommand(std::path::PathBuf, &'static str),
+    #[error("Invalid template `{0}`: {1}")]
+    InvalidTemplate(String, &'static str),
+    #[error("Dynamic mapping of `{0}`: {1}")]
+    InvalidDynamicMapping(String, &'static str),
+    #[error("Dynamic mappings need mapping.dynamic.uid_range")]
+    NoUidRange,
+    #[error("Invalid uid range {0}-{1}")]
+    InvalidUidRange(u32, u32),
+    #[error("No free uid left in mapping.dynamic.uid_range")]
+    UidRangeExhausted,
+    #[error("Database directory of `{0}` does not exist")]
+    InvalidDbPath(std::path::PathBuf),
+    #[error("Local user `{0}` does not exist, its uid, group, gid, home and shell must be set")]
+    UnknownLocalUser(String),
+    #[error("Local group with gid {0} does not exist")]
+    UnknownLocalGroup(u32),
+    #[error("Local user `{0}` has {1} `{3}`, not `{2}`")]
+    AccountMismatch(String, &'static str, String, String),
+    #[error("Local group with gid {0} is `{2}`, not `{1}`")]
+    GroupMismatch(u32, String, String),
+    #[error("Cannot read {}: {1}", .0.display())]
+    LocalAccounts(std::path::PathBuf, std::io::Error),
+    #[error("Insecure permissions on `{}`: {1}", .0.display())]
+    InsecurePermissions(std::path::PathBuf, &'static str),
+    #[error("{}: {1}", .0.display())]
+    ConfigFile(std::path::PathBuf, Box<Error>),
+    #[error("Invalid configuration: {}", join(.0))]
+    InvalidConfig(Vec<Error>),
+    #[error("Invalid configuration: {}", join(.0))]
+    UnknownFields(Vec<UnknownField>),
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
 }
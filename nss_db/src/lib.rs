@@ -1,12 +1,27 @@
 mod log;
+mod command;
 mod db;
 mod config;
+mod mapping;
+mod matcher;
+mod overrides;
+mod passwd;
+mod template;
 mod error;
+mod session;
 mod user;
 
 pub use crate::log::setup_log;
-pub use config::Config;
+pub use config::{
+    Config, Decision, Dynamic, MappingCommand, Override, Overrides,
+    PermissionCheck, UidAllocation, Unmatched, UserMapping,
+};
+pub use mapping::{Check, Condition, Explanation, RuleTrace};
+pub use matcher::{AllowList, Matcher, Pattern, ValueMatch};
+pub use passwd::{Accounts, LocalGroup, LocalUser};
+pub use template::Template;
 pub use db::Db;
-pub use user::User;
+pub use session::Session;
+pub use user::{Account, User};
 pub use error::Error;
 
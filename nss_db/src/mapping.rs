@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fmt;
+
+use common::serde::{decode_opt_attr, encode_opt_attr};
+
+use crate::config::{Decision, UserMapping};
+use crate::matcher::{display_value, ValueMatch};
+use crate::template::Template;
+
+/// Condition on an authenticated RADIUS user.
+///
+/// Every field which is set must match, a condition without any field
+/// matches every user.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Condition {
+    /// All the conditions match
+    pub all: Option<Vec<Condition>>,
+    /// At least one of the conditions matches
+    pub any: Option<Vec<Condition>>,
+    /// The condition does not match
+    pub not: Option<Box<Condition>>,
+    /// RADIUS username, including the realm
+    pub username: Option<String>,
+    /// Realm of the username, `user@realm` or `REALM\user`, compared case
+    /// insensitively
+    pub realm: Option<String>,
+    /// The user has this attribute, with `value` if set
+    #[serde(
+        default,
+        serialize_with = "encode_opt_attr",
+        deserialize_with = "decode_opt_attr"
+    )]
+    pub attribute: Option<(u32, u8)>,
+    pub value: Option<ValueMatch>,
+}
+
+impl Condition {
+    pub fn matches(&self, user: &radius::User) -> bool {
+        self.check(user).matched
+    }
+
+    /// Evaluate every field of the condition, even after one did not match
+    pub fn check(&self, user: &radius::User) -> Check {
+        let mut checks = vec![];
+
+        if let Some(all) = &self.all {
+            let children: Vec<_> = all.iter().map(|c| c.check(user)).collect();
+            let matched = children.iter().all(|c| c.matched);
+            checks.push(Check::group("all of", matched, children));
+        }
+
+        if let Some(any) = &self.any {
+            let children: Vec<_> = any.iter().map(|c| c.check(user)).collect();
+            let matched = children.iter().any(|c| c.matched);
+            checks.push(Check::group("any of", matched, children));
+        }
+
+        if let Some(not) = &self.not {
+            let child = not.check(user);
+            checks.push(Check::group("not", !child.matched, vec![child]));
+        }
+
+        if let Some(username) = &self.username {
+            checks.push(Check::leaf(
+                format!("username is `{}`, got `{}`", username, user.username),
+                username == &user.username,
+            ));
+        }
+
+        if let Some(expected) = &self.realm {
+            let (got, matched) = match realm(&user.username) {
+                Some(realm) => (
+                    format!("`{}`", realm),
+                    realm.eq_ignore_ascii_case(expected),
+                ),
+                None => ("none".to_string(), false),
+            };
+            checks.push(Check::leaf(
+                format!("realm is `{}`, got {}", expected, got),
+                matched,
+            ));
+        }
+
+        if let Some(attribute) = self.attribute {
+            checks.push(check_attribute(user, attribute, self.value.as_ref()));
+        }
+
+        if checks.is_empty() {
+            return Check::leaf("empty condition, matches every user", true);
+        }
+        Check::all(checks)
+    }
+
+    /// Whether this condition or one of its children sets `value` without
+    /// `attribute`, the value would be ignored
+    pub fn has_value_without_attribute(&self) -> bool {
+        if self.value.is_some() && self.attribute.is_none() {
+            return true;
+        }
+        self.all
+            .iter()
+            .chain(self.any.iter())
+            .flatten()
+            .chain(self.not.as_deref())
+            .any(|child| child.has_value_without_attribute())
+    }
+
+    /// Attributes used by this condition and its children
+    pub fn attributes(&self) -> Vec<(u32, u8)> {
+        let mut attributes: Vec<_> = self.attribute.into_iter().collect();
+        let children = self
+            .all
+            .iter()
+            .chain(self.any.iter())
+            .flatten()
+            .chain(self.not.as_deref());
+        for child in children {
+            attributes.extend(child.attributes());
+        }
+        attributes
+    }
+}
+
+impl UserMapping {
+    /// Whether the user matches the `attribute` and `attribute_value` pair
+    /// and the `when` condition. A mapping without any condition never
+    /// matches.
+    pub fn matches(&self, user: &radius::User) -> bool {
+        self.check(user).matched
+    }
+
+    /// Evaluate the conditions of the mapping
+    pub fn check(&self, user: &radius::User) -> Check {
+        if !self.has_condition() {
+            return Check::leaf("no condition, only used as a fallback", false);
+        }
+
+        let mut checks = vec![];
+        if let Some(attribute) = self.attribute {
+            let value = self.attribute_value.as_ref();
+            checks.push(check_attribute(user, attribute, value));
+        }
+        if let Some(when) = &self.when {
+            checks.push(when.check(user));
+        }
+        Check::all(checks)
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic.unwrap_or(false)
+    }
+
+    pub fn has_condition(&self) -> bool {
+        self.attribute.is_some() || self.when.is_some()
+    }
+
+    /// Name of the mapping, or its key in the config
+    pub fn label(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("mapping `{}`", name),
+            None => format!("mapping.users[{}]", index),
+        }
+    }
+
+    /// Attributes used by the conditions, the templates and the overrides of
+    /// this mapping
+    pub fn attributes(&self) -> Vec<(u32, u8)> {
+        let mut attributes: Vec<_> = self.attribute.into_iter().collect();
+        if let Some(when) = &self.when {
+            attributes.extend(when.attributes());
+        }
+        if let Some(overrides) = &self.overrides {
+            attributes.extend(overrides.attributes());
+        }
+        let username = Some(&self.username).filter(|_| self.is_dynamic());
+        let templates =
+            username.into_iter().chain(&self.home).chain(&self.shell);
+        for template in templates.filter_map(|t| Template::parse(t).ok()) {
+            attributes.extend(template.attributes());
+        }
+        attributes
+    }
+}
+
+/// Outcome of a check made to choose a mapping, with the checks it is made
+/// of
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub description: String,
+    pub matched: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Check {
+    fn leaf<S: Into<String>>(description: S, matched: bool) -> Self {
+        Check {
+            description: description.into(),
+            matched,
+            checks: vec![],
+        }
+    }
+
+    fn group(description: &str, matched: bool, checks: Vec<Check>) -> Self {
+        Check {
+            description: description.to_string(),
+            matched,
+            checks,
+        }
+    }
+
+    /// Checks which must all match, a single check is not wrapped
+    fn all(mut checks: Vec<Check>) -> Self {
+        if checks.len() == 1 {
+            return checks.remove(0);
+        }
+        let matched = checks.iter().all(|c| c.matched);
+        Check::group("all of", matched, checks)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let mark = if self.matched { "x" } else { " " };
+        let indent = "  ".repeat(depth);
+        writeln!(f, "{}[{}] {}", indent, mark, self.description)?;
+        for check in &self.checks {
+            check.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Mapping rule evaluated for a user
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleTrace {
+    /// Name of the mapping, or its key in the config
+    pub label: String,
+    /// Local account of the mapping
+    pub username: String,
+    pub priority: i32,
+    pub check: Check,
+}
+
+/// Every mapping rule evaluated for a user, and the resulting decision
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub rules: Vec<RuleTrace>,
+    pub decision: Decision,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rule in &self.rules {
+            let outcome = if rule.check.matched {
+                "matched"
+            } else {
+                "not matched"
+            };
+            writeln!(
+                f,
+                "{} to `{}` with priority {}: {}",
+                rule.label, rule.username, rule.priority, outcome
+            )?;
+            rule.check.write(f, 1)?;
+        }
+
+        writeln!(f, "Decision: {}", self.decision.reason)?;
+        match &self.decision.mapping {
+            Some(m) => {
+                write!(f, "Account: {}", m.username)?;
+                let fields = [
+                    ("uid", m.uid.map(|uid| uid.to_string())),
+                    ("group", m.group.clone()),
+                    ("gid", m.gid.map(|gid| gid.to_string())),
+                    ("home", m.home.clone()),
+                    ("shell", m.shell.clone()),
+                ];
+                for (name, value) in fields.iter() {
+                    if let Some(value) = value {
+                        write!(f, ", {} {}", name, value)?;
+                    }
+                }
+                let account = &self.decision.account;
+                if !account.groups.is_empty() {
+                    let names: Vec<_> = account
+                        .groups
+                        .iter()
+                        .map(|g| g.name.as_str())
+                        .collect();
+                    write!(f, ", groups {}", names.join(","))?;
+                }
+                if let Some(timeout) = account.session_timeout {
+                    write!(f, ", session timeout {}s", timeout)?;
+                }
+                if let Some(max) = account.max_sessions {
+                    write!(f, ", max sessions {}", max)?;
+                }
+                writeln!(f)
+            }
+            None => writeln!(f, "Account: none, the user is denied"),
+        }
+    }
+}
+
+/// Matching mapping with the highest priority and its index, the first one
+/// listed in the config on a tie
+pub fn find_mapping<'a>(
+    mappings: &'a [UserMapping],
+    user: &radius::User,
+) -> Option<(usize, &'a UserMapping)> {
+    mappings
+        .iter()
+        .enumerate()
+        .filter(|(_, mapping)| mapping.matches(user))
+        .max_by_key(|(i, m)| (m.priority.unwrap_or(0), Reverse(*i)))
+}
+
+/// Whether a name can be used in a passwd entry
+pub fn is_valid_username(name: &str) -> bool {
+    !name.is_empty()
+        && name != "root"
+        && !name.starts_with('-')
+        && !name.chars().any(|c| {
+            c == ':' || c == '/' || c.is_whitespace() || c.is_control()
+        })
+}
+
+/// Realm of a `user@realm` or `REALM\user` username
+pub fn realm(username: &str) -> Option<&str> {
+    if let Some(at) = username.rfind('@') {
+        Some(&username[at + 1..])
+    } else {
+        username.find('\\').map(|slash| &username[..slash])
+    }
+}
+
+fn check_attribute(
+    user: &radius::User,
+    attribute: (u32, u8),
+    value: Option<&ValueMatch>,
+) -> Check {
+    let values: Vec<&[u8]> = user
+        .attributes
+        .iter()
+        .filter(|attr| {
+            attr.vendor == attribute.0 && attr.subtype == attribute.1
+        })
+        .map(|attr| attr.data.as_slice())
+        .collect();
+
+    let matched = values.iter().any(|data| match value {
+        Some(value) => value.matches(data),
+        None => true,
+    });
+
+    let expected = match value {
+        Some(value) => value.to_string(),
+        None => "is returned".to_string(),
+    };
+    let got = if values.is_empty() {
+        "not returned".to_string()
+    } else {
+        let values: Vec<_> = values.iter().map(|v| display_value(v)).collect();
+        format!("got {}", values.join(", "))
+    };
+
+    Check::leaf(
+        format!(
+            "attribute {}.{} {}, {}",
+            attribute.0, attribute.1, expected, got
+        ),
+        matched,
+    )
+}
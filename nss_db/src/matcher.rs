@@ -0,0 +1,298 @@
+use regex::bytes::Regex;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::convert::TryInto;
+use std::fmt;
+
+/// Match on an attribute value, either the exact bytes or a typed matcher
+#[derive(Clone, Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ValueMatch {
+    Bytes(Vec<u8>),
+    Matcher(Matcher),
+}
+
+/// Typed match on an attribute value, every field which is set must match.
+///
+/// String matchers compare the value as UTF-8 and never match other values.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Matcher {
+    /// The value is equal
+    pub string: Option<String>,
+    /// The value starts with
+    pub prefix: Option<String>,
+    /// The regex matches the value, it is not anchored
+    pub regex: Option<Pattern>,
+    /// The value is an integer between min and max, inclusive
+    pub range: Option<(i64, i64)>,
+    /// The value is equal to one of the strings
+    #[serde(rename = "in")]
+    pub one_of: Option<Vec<String>>,
+    /// Compare `string`, `prefix` and `in` ignoring ASCII case
+    pub ignore_case: Option<bool>,
+}
+
+/// Values accepted by an override. Unlike in conditions, a regex must match
+/// the whole value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AllowList {
+    pub value: ValueMatch,
+    /// The regex of the matcher anchored at both ends
+    anchored: Option<Pattern>,
+}
+
+/// Regex kept with its source, so it can be compared and serialized
+#[derive(Clone, Debug)]
+pub struct Pattern(pub Regex);
+
+impl ValueMatch {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            ValueMatch::Bytes(bytes) => bytes.as_slice() == data,
+            ValueMatch::Matcher(matcher) => matcher.matches(data),
+        }
+    }
+}
+
+impl AllowList {
+    pub fn new(value: ValueMatch) -> Result<Self, regex::Error> {
+        let anchored = match &value {
+            ValueMatch::Matcher(Matcher {
+                regex: Some(regex), ..
+            }) => {
+                let source = format!("^(?:{})$", regex.0.as_str());
+                Some(Pattern(Regex::new(&source)?))
+            }
+            _ => None,
+        };
+        Ok(AllowList { value, anchored })
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let whole = match &self.anchored {
+            Some(anchored) => anchored.0.is_match(data),
+            None => true,
+        };
+        whole && self.value.matches(data)
+    }
+
+    /// Whether every value is accepted
+    pub fn accepts_any(&self) -> bool {
+        match &self.value {
+            ValueMatch::Matcher(matcher) => matcher.accepts_any(),
+            ValueMatch::Bytes(_) => false,
+        }
+    }
+}
+
+impl Matcher {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let ignore_case = self.ignore_case.unwrap_or(false);
+        let text = std::str::from_utf8(data).ok();
+
+        let eq = |expected: &str| match text {
+            Some(text) if ignore_case => text.eq_ignore_ascii_case(expected),
+            Some(text) => text == expected,
+            None => false,
+        };
+
+        if let Some(string) = &self.string {
+            if !eq(string) {
+                return false;
+            }
+        }
+
+        if let Some(prefix) = &self.prefix {
+            let starts = match text.and_then(|t| t.get(..prefix.len())) {
+                Some(start) if ignore_case => {
+                    start.eq_ignore_ascii_case(prefix)
+                }
+                Some(start) => start == prefix,
+                None => false,
+            };
+            if !starts {
+                return false;
+            }
+        }
+
+        if let Some(regex) = &self.regex {
+            if !regex.0.is_match(data) {
+                return false;
+            }
+        }
+
+        if let Some((min, max)) = self.range {
+            match integer(data) {
+                Some(n) if n >= min && n <= max => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(one_of) = &self.one_of {
+            if !one_of.iter().any(|expected| eq(expected)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Whether no matcher is set, `ignore_case` alone matches every value
+    pub fn accepts_any(&self) -> bool {
+        self.string.is_none()
+            && self.prefix.is_none()
+            && self.regex.is_none()
+            && self.range.is_none()
+            && self.one_of.is_none()
+    }
+}
+
+impl fmt::Display for ValueMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueMatch::Bytes(bytes) => {
+                write!(f, "is {}", display_value(bytes))
+            }
+            ValueMatch::Matcher(matcher) => matcher.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(string) = &self.string {
+            parts.push(format!("is {:?}", string));
+        }
+        if let Some(prefix) = &self.prefix {
+            parts.push(format!("starts with {:?}", prefix));
+        }
+        if let Some(regex) = &self.regex {
+            parts.push(format!("matches /{}/", regex.0.as_str()));
+        }
+        if let Some((min, max)) = self.range {
+            parts.push(format!("is between {} and {}", min, max));
+        }
+        if let Some(one_of) = &self.one_of {
+            parts.push(format!("is one of {:?}", one_of));
+        }
+
+        if parts.is_empty() {
+            f.write_str("has any value")?;
+        } else {
+            f.write_str(&parts.join(" and "))?;
+        }
+        if self.ignore_case.unwrap_or(false) {
+            f.write_str(" ignoring case")?;
+        }
+        Ok(())
+    }
+}
+
+/// Attribute value quoted if it is printable text, in hexadecimal otherwise
+pub(crate) fn display_value(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.chars().any(char::is_control) => {
+            format!("{:?}", text)
+        }
+        _ => {
+            let hex: String =
+                data.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("0x{}", hex)
+        }
+    }
+}
+
+/// Integer value of an attribute, written in decimal or as a 1, 2, 4 or 8
+/// bytes big endian integer
+pub(crate) fn integer(data: &[u8]) -> Option<i64> {
+    let decimal = std::str::from_utf8(data)
+        .ok()
+        .filter(|s| !s.is_empty())
+        .and_then(|s| s.parse().ok());
+
+    decimal.or_else(|| match data.len() {
+        1 => Some(data[0] as i64),
+        2 => Some(u16::from_be_bytes(data.try_into().ok()?) as i64),
+        4 => Some(u32::from_be_bytes(data.try_into().ok()?) as i64),
+        8 => Some(i64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    })
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Regex::new(&s).map(Pattern).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for AllowList {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AllowList {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let value = ValueMatch::deserialize(deserializer)?;
+        AllowList::new(value).map_err(de::Error::custom)
+    }
+}
+
+/// A byte array is an exact match, a table is a `Matcher`
+impl<'de> Deserialize<'de> for ValueMatch {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        struct ValueMatchVisitor;
+
+        impl<'de> Visitor<'de> for ValueMatchVisitor {
+            type Value = ValueMatch;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte array or a matcher table")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(de::value::SeqAccessDeserializer::new(seq))
+                    .map(ValueMatch::Bytes)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<Self::Value, A::Error> {
+                Matcher::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(ValueMatch::Matcher)
+            }
+        }
+
+        deserializer.deserialize_any(ValueMatchVisitor)
+    }
+}
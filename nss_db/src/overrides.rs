@@ -0,0 +1,172 @@
+use std::convert::TryFrom;
+
+use crate::config::{Override, Overrides, UserMapping};
+use crate::matcher::{display_value, integer};
+use crate::passwd::Accounts;
+use crate::user::Account;
+
+impl Overrides {
+    /// Attributes read by the overrides
+    pub fn attributes(&self) -> Vec<(u32, u8)> {
+        self.fields()
+            .iter()
+            .filter_map(|(_, field)| field.map(|o| o.attribute))
+            .collect()
+    }
+
+    /// Replace the shell and home of the mapping by the returned values, and
+    /// take the supplementary groups and session limits. The reason is
+    /// returned when a value is not allowed.
+    pub(crate) fn apply(
+        &self,
+        mapping: &mut UserMapping,
+        user: &radius::User,
+        accounts: &Accounts,
+    ) -> Result<Account, String> {
+        let mut account = Account::default();
+
+        if let Some(shell) = &self.shell {
+            if let Some(data) = shell.values("shell", user)?.first() {
+                mapping.shell = Some(path("shell", shell, data)?);
+            }
+        }
+
+        if let Some(home) = &self.home {
+            if let Some(data) = home.values("home", user)?.first() {
+                mapping.home = Some(path("home", home, data)?);
+            }
+        }
+
+        if let Some(groups) = &self.groups {
+            for data in groups.values("group", user)? {
+                let group = std::str::from_utf8(data)
+                    .ok()
+                    .and_then(|name| accounts.group_by_name(name))
+                    .ok_or_else(|| {
+                        format!(
+                            "group {} of {} is not a local group",
+                            display_value(data),
+                            groups.label()
+                        )
+                    })?;
+                if !account.groups.contains(group) {
+                    account.groups.push(group.clone());
+                }
+            }
+        }
+
+        if let Some(timeout) = &self.session_timeout {
+            if let Some(data) = timeout.values("session timeout", user)?.first()
+            {
+                account.session_timeout =
+                    Some(number("session timeout", timeout, data)?);
+            }
+        }
+
+        if let Some(max) = &self.max_sessions {
+            if let Some(data) = max.values("max sessions", user)?.first() {
+                account.max_sessions = Some(number("max sessions", max, data)?);
+            }
+        }
+
+        Ok(account)
+    }
+
+    fn fields(&self) -> [(&'static str, Option<&Override>); 5] {
+        [
+            ("shell", self.shell.as_ref()),
+            ("home", self.home.as_ref()),
+            ("groups", self.groups.as_ref()),
+            ("session_timeout", self.session_timeout.as_ref()),
+            ("max_sessions", self.max_sessions.as_ref()),
+        ]
+    }
+
+    /// Overrides which accept any value
+    pub(crate) fn unrestricted(&self) -> Vec<&'static str> {
+        self.fields()
+            .iter()
+            .filter(|(_, field)| match field {
+                Some(o) => o.allow.accepts_any(),
+                None => false,
+            })
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl Override {
+    /// Values of the attribute, which must all be allowed. An override
+    /// without allow-list is rejected by the config validation, it denies
+    /// the user if the config was not validated.
+    fn values<'a>(
+        &self,
+        name: &str,
+        user: &'a radius::User,
+    ) -> Result<Vec<&'a [u8]>, String> {
+        if self.allow.accepts_any() {
+            return Err(format!(
+                "{} of {} has no allow-list",
+                name,
+                self.label()
+            ));
+        }
+
+        let (vendor, subtype) = self.attribute;
+        let values: Vec<&[u8]> = user
+            .attributes
+            .iter()
+            .filter(|a| a.vendor == vendor && a.subtype == subtype)
+            .map(|a| a.data.as_slice())
+            .collect();
+
+        match values.iter().find(|data| !self.allow.matches(data)) {
+            Some(data) => Err(format!(
+                "{} {} of {} is not allowed",
+                name,
+                display_value(data),
+                self.label()
+            )),
+            None => Ok(values),
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("attribute {}.{}", self.attribute.0, self.attribute.1)
+    }
+}
+
+/// Absolute path written in a passwd entry
+fn path(name: &str, o: &Override, data: &[u8]) -> Result<String, String> {
+    match std::str::from_utf8(data) {
+        Ok(path)
+            if path.starts_with('/')
+                && !path.chars().any(|c| c == ':' || c.is_control()) =>
+        {
+            Ok(path.to_string())
+        }
+        _ => Err(format!(
+            "{} {} of {} is not an absolute path",
+            name,
+            display_value(data),
+            o.label()
+        )),
+    }
+}
+
+fn number<T: TryFrom<i64>>(
+    name: &str,
+    o: &Override,
+    data: &[u8],
+) -> Result<T, String> {
+    integer(data)
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| {
+            format!(
+                "{} {} of {} is not an unsigned integer",
+                name,
+                display_value(data),
+                o.label()
+            )
+        })
+}
@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::Error;
+
+const PASSWD_PATH: &str = "/etc/passwd";
+const GROUP_PATH: &str = "/etc/group";
+
+/// Entry of /etc/passwd
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+    pub shell: String,
+}
+
+/// Entry of /etc/group
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LocalGroup {
+    pub name: String,
+    pub gid: u32,
+}
+
+/// Local users and groups.
+///
+/// The files are read directly, going through NSS would call this module
+/// again.
+#[derive(Clone, Debug, Default)]
+pub struct Accounts {
+    users: Vec<LocalUser>,
+    groups: Vec<LocalGroup>,
+}
+
+impl Accounts {
+    /// Read /etc/passwd and /etc/group
+    pub fn system() -> Result<Accounts, Error> {
+        Accounts::read(Path::new(PASSWD_PATH), Path::new(GROUP_PATH))
+    }
+
+    pub fn read(passwd: &Path, group: &Path) -> Result<Accounts, Error> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .map_err(|err| Error::LocalAccounts(path.into(), err))
+        };
+        Ok(Accounts::parse(&read(passwd)?, &read(group)?))
+    }
+
+    /// Parse the content of passwd and group files, malformed lines and NIS
+    /// entries are skipped.
+    pub fn parse(passwd: &str, group: &str) -> Accounts {
+        let users = entries(passwd, 7)
+            .filter_map(|fields| {
+                Some(LocalUser {
+                    name: fields[0].to_string(),
+                    uid: fields[2].parse().ok()?,
+                    gid: fields[3].parse().ok()?,
+                    home: fields[5].to_string(),
+                    shell: fields[6].to_string(),
+                })
+            })
+            .collect();
+
+        let groups = entries(group, 4)
+            .filter_map(|fields| {
+                Some(LocalGroup {
+                    name: fields[0].to_string(),
+                    gid: fields[2].parse().ok()?,
+                })
+            })
+            .collect();
+
+        Accounts { users, groups }
+    }
+
+    /// First user with this name, like getpwnam
+    pub fn user(&self, name: &str) -> Option<&LocalUser> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    pub fn uids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.users.iter().map(|user| user.uid)
+    }
+
+    /// First group with this gid, like getgrgid
+    pub fn group(&self, gid: u32) -> Option<&LocalGroup> {
+        self.groups.iter().find(|group| group.gid == gid)
+    }
+
+    /// First group with this name, like getgrnam
+    pub fn group_by_name(&self, name: &str) -> Option<&LocalGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+}
+
+fn entries(text: &str, count: usize) -> impl Iterator<Item = Vec<&str>> {
+    text.lines()
+        .filter(|line| !line.starts_with(&['#', '+', '-'][..]))
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .filter(move |fields| fields.len() == count && !fields[0].is_empty())
+}
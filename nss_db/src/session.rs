@@ -0,0 +1,23 @@
+/// Process name of the shell wrapper
+const SHELL_NAME: &str = "radius_shell";
+
+/// Login session started by `radius_shell`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub session_id: String,
+    pub username: String,
+    pub pid: i64,
+    pub started: i64,
+}
+
+impl Session {
+    /// Check that the session pid still belongs to a shell wrapper, and was
+    /// not reused by another process.
+    pub fn is_running(&self) -> bool {
+        let comm = std::fs::read_to_string(format!("/proc/{}/comm", self.pid));
+        match comm {
+            Ok(comm) => comm.trim_end() == SHELL_NAME,
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use common::attr::parse_attr;
+
+use crate::error::Error;
+
+/// Username, home or shell of a mapping, with placeholders expanded when a
+/// RADIUS user is mapped:
+///
+/// - `%u` the RADIUS username
+/// - `%l` the local username
+/// - `%{attr:spec}` the first value of an attribute, like `%{attr:9.1}` or
+///   `%{attr:Class}`
+/// - `%%` a `%`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    RadiusUser,
+    LocalUser,
+    Attribute((u32, u8)),
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, Error> {
+        let invalid = |reason| Error::InvalidTemplate(template.into(), reason);
+
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+
+            let part = match chars.next() {
+                Some('%') => {
+                    text.push('%');
+                    continue;
+                }
+                Some('u') => Part::RadiusUser,
+                Some('l') => Part::LocalUser,
+                Some('{') => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => inner.push(c),
+                            None => return Err(invalid("missing `}`")),
+                        }
+                    }
+                    let spec = inner
+                        .strip_prefix("attr:")
+                        .ok_or_else(|| invalid("expected `%{attr:spec}`"))?;
+                    let attr = parse_attr(spec)
+                        .map_err(|_| invalid("bad attribute"))?;
+                    Part::Attribute(attr)
+                }
+                _ => return Err(invalid("expected `%u`, `%l`, `%{` or `%%`")),
+            };
+
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(part);
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Template { parts })
+    }
+
+    pub fn uses_local_user(&self) -> bool {
+        self.parts.contains(&Part::LocalUser)
+    }
+
+    /// Attributes used by the placeholders
+    pub fn attributes(&self) -> Vec<(u32, u8)> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Attribute(attr) => Some(*attr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Expand the placeholders, the reason is returned when a value is
+    /// missing or could escape its path component.
+    pub fn expand(
+        &self,
+        user: &radius::User,
+        local: Option<&str>,
+    ) -> Result<String, String> {
+        let mut expanded = String::new();
+
+        for part in &self.parts {
+            let value = match part {
+                Part::Text(text) => {
+                    expanded.push_str(text);
+                    continue;
+                }
+                Part::RadiusUser => user.username.as_str(),
+                Part::LocalUser => {
+                    local.ok_or("the local username is not known yet")?
+                }
+                Part::Attribute((vendor, subtype)) => {
+                    let attr = user
+                        .attributes
+                        .iter()
+                        .find(|a| a.vendor == *vendor && a.subtype == *subtype)
+                        .ok_or_else(|| {
+                            format!(
+                                "attribute {}.{} is not returned",
+                                vendor, subtype
+                            )
+                        })?;
+                    std::str::from_utf8(&attr.data).map_err(|_| {
+                        format!("attribute {}.{} is not text", vendor, subtype)
+                    })?
+                }
+            };
+
+            if !is_safe(value) {
+                return Err(format!("`{}` cannot be used in a path", value));
+            }
+            expanded.push_str(value);
+        }
+
+        Ok(expanded)
+    }
+}
+
+/// Values are inserted in paths and passwd entries, they must not add path
+/// components or fields
+fn is_safe(value: &str) -> bool {
+    !value.is_empty()
+        && value != "."
+        && value != ".."
+        && !value
+            .chars()
+            .any(|c| c == '/' || c == ':' || c.is_control())
+}
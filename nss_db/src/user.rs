@@ -1,8 +1,23 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+use crate::passwd::LocalGroup;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct User {
     pub radius: radius::User,
     pub mapping: crate::config::UserMapping,
+    #[serde(default)]
+    pub account: Account,
+}
+
+/// Supplementary groups and session limits of a mapped user, taken from
+/// reply attributes
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Account {
+    pub groups: Vec<LocalGroup>,
+    /// Seconds after which the sessions are hung up
+    pub session_timeout: Option<u64>,
+    /// Sessions which can be open at the same time
+    pub max_sessions: Option<u32>,
 }
 
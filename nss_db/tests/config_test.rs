@@ -0,0 +1,356 @@
+use nss_db::Accounts;
+use nss_db::Config;
+use nss_db::Error;
+use nss_db::Template;
+
+mod helpers;
+use helpers::*;
+
+#[test]
+fn it_validates_config() -> Result<(), Error> {
+    config()?.validate()
+}
+
+#[test]
+fn it_rejects_unknown_fields() -> Result<(), Error> {
+    let text = std::fs::read_to_string("../tests/config.toml")?
+        .replace("timeout = 3", "timeout = 3\ntimeouts = 3");
+    let path = std::env::temp_dir()
+        .join(format!("radius_auth_virtual_{}.toml", std::process::id()));
+    std::fs::write(&path, text)?;
+
+    let res = Config::read_file_strict(&path);
+    std::fs::remove_file(&path)?;
+
+    match res {
+        Err(Error::UnknownFields(fields)) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].key, "radius.servers[0].timeouts");
+            assert_eq!(fields[0].path, Some(path));
+            assert_eq!(fields[0].location, Some((10, 1)));
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_merges_drop_in_fragments() -> Result<(), Error> {
+    let dir = std::env::temp_dir()
+        .join(format!("radius_auth_virtual_{}.d", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let team = dir.join("20-team.toml");
+    std::fs::write(
+        dir.join("10-secret.toml"),
+        "[radius]\nshared_secret = \"fragment\"\n",
+    )?;
+    std::fs::write(
+        &team,
+        "[[mapping.users]]\n\
+         username = \"teamuser\"\n\
+         uid = 1020\n\
+         group = \"team\"\n\
+         gid = 1020\n\
+         home = \"/home/teamuser\"\n\
+         shell = \"/bin/bash\"\n\
+         attribute = \"1.2\"\n\
+         attribute_value = [0xCC]\n",
+    )?;
+    std::fs::write(dir.join("30-ignored.conf"), "invalid")?;
+
+    let res = Config::read_with_dir("../tests/config.toml", &dir);
+    std::fs::remove_dir_all(&dir)?;
+    let mut config = res?;
+
+    assert_eq!(config.radius.shared_secret, Some("fragment".to_string()));
+    let users: Vec<_> = config
+        .mapping
+        .users
+        .iter()
+        .map(|user| user.username.as_str())
+        .collect();
+    assert_eq!(users, vec!["adminuser", "normaluser", "teamuser"]);
+    assert_eq!(config.sources.files().len(), 3);
+    assert_eq!(config.sources.file_of("mapping.users[2]"), Some(&*team));
+
+    // The fragment uses an attribute which is not requested
+    config.mapping.db.path = std::env::temp_dir().join("radius.db");
+    match config.validate() {
+        Err(Error::InvalidConfig(errors)) => match &errors[..] {
+            [Error::ConfigFile(path, err)] => {
+                assert_eq!(path, &team);
+                assert!(matches!(**err, Error::UnrequestedAttribute(..)));
+            }
+            errors => panic!("Unexpected errors {:?}", errors),
+        },
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_locates_errors_of_drop_in_fragments() -> Result<(), Error> {
+    let dir = std::env::temp_dir()
+        .join(format!("radius_auth_virtual_{}_bad.d", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let bad = dir.join("10-bad.toml");
+    std::fs::write(&bad, "[radius]\n\nattributes = [\"1.1\", \"1,1\"]\n")?;
+
+    let res = Config::read_with_dir("../tests/config.toml", &dir);
+    std::fs::remove_dir_all(&dir)?;
+
+    match res {
+        Err(Error::ConfigFile(path, err)) => {
+            assert_eq!(path, bad);
+            match *err {
+                Error::TomlError(err) => {
+                    assert!(err.to_string().contains("`1,1`"), "{}", err);
+                    assert_eq!(
+                        err.line_col().map(|(line, _)| line),
+                        Some(2),
+                        "{}",
+                        err
+                    );
+                }
+                err => panic!("Unexpected error {:?}", err),
+            }
+        }
+        Err(err) => panic!("Unexpected error {:?}", err),
+        Ok(_) => panic!("Config with an invalid attribute was loaded"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_rejects_insecure_permissions() -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!(
+        "radius_auth_virtual_{}_insecure.toml",
+        std::process::id()
+    ));
+    std::fs::copy("../tests/config.toml", &path)?;
+    std::fs::set_permissions(&path, PermissionsExt::from_mode(0o666))?;
+
+    let res = Config::read_file(&path).and_then(|c| c.check_permissions());
+    std::fs::remove_file(&path)?;
+
+    match res {
+        Err(Error::InsecurePermissions(p, _)) => assert_eq!(p, path),
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+const PASSWD: &str = "\
+root:x:0:0:root:/root:/bin/bash
+adminuser:x:1010:1010::/home/adminuser:/bin/zsh
+normaluser:x:1011:100::/home/normaluser:/bin/bash
+";
+
+const GROUP: &str = "\
+root:x:0:
+admingroup:x:1010:
+users:x:100:normaluser
+";
+
+#[test]
+fn it_resolves_local_accounts() -> Result<(), Error> {
+    let mut config = config()?;
+    config.mapping.users.truncate(1);
+    let adminuser = &mut config.mapping.users[0];
+    adminuser.uid = None;
+    adminuser.group = None;
+    adminuser.gid = None;
+    adminuser.home = None;
+    adminuser.shell = None;
+
+    config.resolve_accounts(&Accounts::parse(PASSWD, GROUP))?;
+
+    let adminuser = &config.mapping.users[0];
+    assert_eq!(adminuser.uid, Some(1010));
+    assert_eq!(adminuser.group.as_deref(), Some("admingroup"));
+    assert_eq!(adminuser.gid, Some(1010));
+    assert_eq!(adminuser.home.as_deref(), Some("/home/adminuser"));
+    assert_eq!(adminuser.shell.as_deref(), Some("/bin/zsh"));
+
+    // The default user does not exist locally and sets every field
+    assert_eq!(config.mapping.default_user.uid, Some(1012));
+    Ok(())
+}
+
+#[test]
+fn it_rejects_account_mismatch() -> Result<(), Error> {
+    let mut config = config()?;
+    config.mapping.default_user.home = None;
+
+    match config.resolve_accounts(&Accounts::parse(PASSWD, GROUP)) {
+        Err(Error::InvalidConfig(errors)) => {
+            let errors: Vec<_> =
+                errors.iter().map(|err| err.to_string()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    "Local user `radius` does not exist, its uid, group, \
+                     gid, home and shell must be set",
+                    "Local user `adminuser` has shell `/bin/zsh`, not \
+                     `/bin/bash`",
+                    "Local user `normaluser` has gid `100`, not `1011`",
+                    "Local user `normaluser` has group `users`, not \
+                     `normalgroup`",
+                ]
+            );
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_validates_dynamic_mappings() -> Result<(), Error> {
+    let mut config = config()?;
+    let mapping = &mut config.mapping.users[0];
+    mapping.dynamic = Some(true);
+    mapping.username = "%u".to_string();
+
+    match config.validate() {
+        Err(Error::InvalidConfig(errors)) => {
+            let errors: Vec<_> =
+                errors.iter().map(|err| err.to_string()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    "Dynamic mapping of `%u`: the uid is allocated and \
+                     cannot be set",
+                    "Dynamic mappings need mapping.dynamic.uid_range",
+                ]
+            );
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_validates_templates() -> Result<(), Error> {
+    let mut config = config()?;
+    let mapping = &mut config.mapping.users[0];
+    mapping.home = Some("/home/%x".to_string());
+    mapping.shell = Some("/bin/%{attr:9.1}".to_string());
+
+    match config.validate() {
+        Err(Error::InvalidConfig(errors)) => {
+            let errors: Vec<_> =
+                errors.iter().map(|err| err.to_string()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    "Invalid template `/home/%x`: expected `%u`, `%l`, `%{` \
+                     or `%%`",
+                    "Mapping of `adminuser` uses attribute 9.1 which is not \
+                     listed in radius.attributes",
+                ]
+            );
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_rejects_unclosed_template_attributes() {
+    match Template::parse("/srv/%{attr:1.1") {
+        Err(err) => assert_eq!(
+            err.to_string(),
+            "Invalid template `/srv/%{attr:1.1`: missing `}`"
+        ),
+        res => panic!("Unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn it_requires_override_allow_lists() -> Result<(), Error> {
+    let mut config = config()?;
+    config.mapping.users[0].overrides = Some(toml::from_str(
+        r#"shell = { attribute = "1.1", allow = { ignore_case = true } }"#,
+    )?);
+
+    match config.validate() {
+        Err(Error::InvalidConfig(errors)) => {
+            let errors: Vec<_> =
+                errors.iter().map(|err| err.to_string()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    "Mapping of `adminuser` overrides shell with any value, \
+                     set an allow-list"
+                ]
+            );
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_validates_mapping_without_servers() -> Result<(), Error> {
+    let mut config = config()?;
+    config.radius.servers.clear();
+    config.validate_mapping()?;
+
+    config.mapping.users[0].when = Some(toml::from_str(
+        r#"any = [{ value = { string = "admin" } }]"#,
+    )?);
+
+    match config.validate_mapping() {
+        Err(Error::InvalidConfig(errors)) => {
+            let errors: Vec<_> =
+                errors.iter().map(|err| err.to_string()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    "Mapping of `adminuser` has a condition with a value but \
+                     no attribute"
+                ]
+            );
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_rejects_values_without_attribute() -> Result<(), Error> {
+    let mut config = config()?;
+    config.mapping.users[0].when = Some(toml::from_str(
+        r#"any = [{ value = { string = "admin" } }]"#,
+    )?);
+
+    match config.validate() {
+        Err(Error::InvalidConfig(errors)) => {
+            let errors: Vec<_> =
+                errors.iter().map(|err| err.to_string()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    "Mapping of `adminuser` has a condition with a value but \
+                     no attribute"
+                ]
+            );
+        }
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    Ok(())
+}
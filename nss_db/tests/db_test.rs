@@ -1,4 +1,5 @@
 use nss_db::Db;
+use nss_db::Dynamic;
 use nss_db::Error;
 use radius::User;
 use radius::Attribute;
@@ -26,3 +27,127 @@ fn it_store_user() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn it_updates_user_and_keeps_cookie() -> Result<(), Error> {
+    let conf = config()?;
+
+    let mut db = Db::with_config(&conf)?;
+    let mut user = User::new("updated");
+    user.attributes.push(Attribute {
+        vendor: 1,
+        subtype: 1,
+        data: vec![0xAA],
+    });
+    let mut user = conf.map_user(&user).ok_or(Error::UserNotFound)?;
+    let cookie = db.store_user(&user)?;
+
+    user.account.session_timeout = Some(60);
+    db.update_user(&user)?;
+
+    let user_r = db.get_user_with_cookie("updated", cookie.as_str())?;
+    assert_eq!(user, user_r);
+    Ok(())
+}
+
+
+#[test]
+fn it_store_session() -> Result<(), Error> {
+    let conf = config()?;
+
+    let mut db = Db::with_config(&conf)?;
+    let session = db.store_session("testing", 42)?;
+
+    let sessions = db.find_sessions("testing")?;
+    assert!(sessions.contains(&session));
+
+    db.delete_session(&session.session_id)?;
+    let sessions = db.find_sessions("testing")?;
+    assert!(!sessions.contains(&session));
+    Ok(())
+}
+
+#[test]
+fn it_allocates_dynamic_uids() -> Result<(), Error> {
+    let mut conf = config()?;
+    conf.mapping.db.path = std::env::temp_dir().join(format!(
+        "radius_auth_virtual_{}_dynamic.db",
+        std::process::id()
+    ));
+    conf.mapping.dynamic = Some(Dynamic {
+        uid_range: (20000, 20001),
+        allocation: None,
+    });
+    let mapping = &mut conf.mapping.users[0];
+    mapping.dynamic = Some(true);
+    mapping.uid = None;
+    mapping.username = "%u".to_string();
+
+    let mut db = Db::with_config(&conf)?;
+    let store = |db: &mut Db, username: &str| -> Result<Option<u32>, Error> {
+        let mut user = User::new(username);
+        user.attributes.push(Attribute {
+            vendor: 1,
+            subtype: 1,
+            data: vec![0xAA],
+        });
+        let user = conf.map_user(&user).ok_or(Error::UserNotFound)?;
+        db.store_user(&user)?;
+        Ok(db.get_user(username)?.mapping.uid)
+    };
+
+    assert_eq!(store(&mut db, "alice")?, Some(20000));
+    assert_eq!(store(&mut db, "bob")?, Some(20001));
+    // The uid is kept across logins
+    assert_eq!(store(&mut db, "alice")?, Some(20000));
+    assert!(matches!(
+        store(&mut db, "carol"),
+        Err(Error::UidRangeExhausted)
+    ));
+
+    assert_eq!(db.get_user_by_uid(20001)?.radius.username, "bob");
+
+    // A revoked user keeps its uid
+    db.revoke_user("bob")?;
+    assert_eq!(db.get_user_by_uid(20001)?.radius.username, "bob");
+    assert_eq!(store(&mut db, "bob")?, Some(20001));
+    std::fs::remove_file(&conf.mapping.db.path)?;
+    Ok(())
+}
+
+#[test]
+fn it_finds_dynamic_users_by_local_name() -> Result<(), Error> {
+    let mut conf = config()?;
+    conf.mapping.db.path = std::env::temp_dir().join(format!(
+        "radius_auth_virtual_{}_local_name.db",
+        std::process::id()
+    ));
+    conf.mapping.dynamic = Some(Dynamic {
+        uid_range: (20000, 20009),
+        allocation: None,
+    });
+    let mapping = &mut conf.mapping.users[0];
+    mapping.dynamic = Some(true);
+    mapping.uid = None;
+    mapping.username = "r_%u".to_string();
+
+    let mut db = Db::with_config(&conf)?;
+    let mut user = User::new("alice");
+    user.attributes.push(Attribute {
+        vendor: 1,
+        subtype: 1,
+        data: vec![0xAA],
+    });
+    let user = conf.map_user(&user).ok_or(Error::UserNotFound)?;
+    db.store_user(&user)?;
+
+    // getpwuid then getpwnam of the returned name give the same account
+    let by_uid = db.get_user_by_uid(20000)?;
+    assert_eq!(by_uid.mapping.username, "r_alice");
+    let by_name = db.find_user(&by_uid.mapping.username)?;
+    assert_eq!(by_name.mapping.uid, Some(20000));
+    assert_eq!(db.find_user("alice")?, by_name);
+    assert!(matches!(db.find_user("r_bob"), Err(Error::UserNotFound)));
+
+    std::fs::remove_file(&conf.mapping.db.path)?;
+    Ok(())
+}
@@ -4,6 +4,9 @@ use nss_db::Error;
 pub fn config() -> Result<Config, Error> {
     let path = std::env::current_dir()?;
     let path = path.join("../tests/config.toml");
-    Config::read_file(path)
+    let mut config = Config::read_file(path)?;
+    // Use a fresh database for each test run
+    config.mapping.db.path = std::env::temp_dir()
+        .join(format!("radius_auth_virtual_{}.db", std::process::id()));
+    Ok(config)
 }
-
@@ -0,0 +1,586 @@
+use nss_db::Account;
+use nss_db::Accounts;
+use nss_db::Config;
+use nss_db::Error;
+use nss_db::MappingCommand;
+use nss_db::Unmatched;
+use nss_db::UserMapping;
+use radius::Attribute;
+use radius::User;
+use serde::Deserialize;
+
+mod helpers;
+use helpers::*;
+
+#[derive(Deserialize)]
+struct Mappings {
+    users: Vec<UserMapping>,
+}
+
+const MAPPINGS: &str = r#"
+[[users]]
+username = "admin"
+uid = 1010
+group = "admin"
+gid = 1010
+home = "/home/admin"
+shell = "/bin/bash"
+priority = 10
+
+[users.when]
+realm = "example.com"
+any = [
+  { attribute = "1.1", value = [0xAA] },
+  { username = "root@example.com" },
+]
+
+[[users]]
+username = "staff"
+uid = 1011
+group = "staff"
+gid = 1011
+home = "/home/staff"
+shell = "/bin/bash"
+when = { realm = "EXAMPLE.COM", not = { attribute = "1.2" } }
+
+[[users]]
+username = "other"
+uid = 1012
+group = "other"
+gid = 1012
+home = "/home/other"
+shell = "/bin/bash"
+when = { realm = "example.com" }
+
+[[users]]
+username = "legacy"
+uid = 1013
+group = "legacy"
+gid = 1013
+home = "/home/legacy"
+shell = "/bin/bash"
+attribute = "1.3"
+attribute_value = [0x01]
+
+[[users]]
+username = "nps"
+uid = 1014
+group = "nps"
+gid = 1014
+home = "/home/nps"
+shell = "/bin/bash"
+when = { attribute = "Class", value = { in = ["ops", "sre"] } }
+
+[[users]]
+name = "restricted"
+username = "restricted"
+uid = 1015
+group = "restricted"
+gid = 1015
+home = "/home/restricted"
+shell = "/bin/rbash"
+"#;
+
+fn user(username: &str, attributes: &[(u8, u8)]) -> User {
+    let mut user = User::new(username);
+    for (subtype, value) in attributes {
+        user.attributes.push(Attribute {
+            vendor: 1,
+            subtype: *subtype,
+            data: vec![*value],
+        });
+    }
+    user
+}
+
+fn mapping_config(unmatched: Option<Unmatched>) -> Result<Config, Error> {
+    let mut conf = config()?;
+    conf.mapping.users = toml::from_str::<Mappings>(MAPPINGS)?.users;
+    conf.mapping.unmatched = unmatched;
+    Ok(conf)
+}
+
+fn mapped(username: &str, attributes: &[(u8, u8)]) -> Result<String, Error> {
+    Ok(mapping_config(None)?
+        .map_user(&user(username, attributes))
+        .map(|user| user.mapping.username)
+        .unwrap_or_default())
+}
+
+#[test]
+fn it_uses_the_highest_priority() -> Result<(), Error> {
+    assert_eq!(mapped("alice@example.com", &[(1, 0xAA)])?, "admin");
+    assert_eq!(mapped("root@example.com", &[])?, "admin");
+    Ok(())
+}
+
+#[test]
+fn it_uses_the_first_mapping_on_a_tie() -> Result<(), Error> {
+    assert_eq!(mapped("alice@example.com", &[])?, "staff");
+    assert_eq!(mapped("alice@example.com", &[(2, 0x01)])?, "other");
+    Ok(())
+}
+
+#[test]
+fn it_matches_realm_prefix() -> Result<(), Error> {
+    assert_eq!(mapped("EXAMPLE.COM\\alice", &[(1, 0xAA)])?, "admin");
+    assert_eq!(mapped("alice@example.org", &[(1, 0xAA)])?, "");
+    Ok(())
+}
+
+#[test]
+fn it_matches_standard_attributes() -> Result<(), Error> {
+    let conf = mapping_config(None)?;
+
+    let mut user = User::new("alice");
+    user.attributes.push(Attribute {
+        vendor: 0,
+        subtype: 25,
+        data: b"sre".to_vec(),
+    });
+
+    let user = conf.map_user(&user).ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.username, "nps");
+    Ok(())
+}
+
+#[test]
+fn it_merges_coa_attributes() -> Result<(), Error> {
+    let conf = mapping_config(None)?;
+    let stored = user("alice@example.com", &[(1, 0xBB)]);
+    let coa = user("alice@example.com", &[(1, 0xAA), (2, 0x01)]);
+
+    // 1.2 is not listed in radius.attributes, it would map to other
+    let merged = conf.merge_attributes(&stored, &coa.attributes[1..]);
+    assert_eq!(merged, stored);
+
+    let merged = conf.merge_attributes(&stored, &coa.attributes);
+    assert_eq!(merged.attributes, coa.attributes[..1]);
+    let user = conf.map_user(&stored).ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.username, "staff");
+    let user = conf.map_user(&merged).ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.username, "admin");
+    Ok(())
+}
+
+#[test]
+fn it_matches_legacy_attribute() -> Result<(), Error> {
+    assert_eq!(mapped("alice", &[(3, 0x01)])?, "legacy");
+    assert_eq!(mapped("alice", &[(3, 0x02)])?, "");
+    Ok(())
+}
+
+#[test]
+fn it_denies_unmatched_users() -> Result<(), Error> {
+    let conf = mapping_config(Some(Unmatched::Deny))?;
+    let decision = conf.decide(&user("bob", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "no mapping matched, unmatched users are denied"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_uses_the_fallback_mapping() -> Result<(), Error> {
+    let conf =
+        mapping_config(Some(Unmatched::Mapping("restricted".to_string())))?;
+    let user = conf
+        .map_user(&user("bob", &[]))
+        .ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.username, "restricted");
+    assert_eq!(user.mapping.shell.as_deref(), Some("/bin/rbash"));
+    Ok(())
+}
+
+#[test]
+fn it_lands_on_the_default_user() -> Result<(), Error> {
+    let mut conf = mapping_config(Some(Unmatched::DefaultUser))?;
+
+    let decision = conf.decide(&user("bob", &[]));
+    assert_eq!(decision.mapping, None);
+
+    conf.mapping.default_user.landing_shell = Some("/bin/sh".to_string());
+    let user = conf
+        .map_user(&user("bob", &[]))
+        .ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.username, "radius");
+    assert_eq!(user.mapping.shell.as_deref(), Some("/bin/sh"));
+    Ok(())
+}
+
+#[test]
+fn it_explains_every_rule() -> Result<(), Error> {
+    let conf = mapping_config(None)?;
+    let explanation = conf.explain_mapping(&user("alice@example.com", &[]));
+
+    let matched: Vec<_> = explanation
+        .rules
+        .iter()
+        .map(|rule| (rule.label.as_str(), rule.check.matched))
+        .collect();
+    assert_eq!(
+        matched,
+        vec![
+            ("mapping.users[0]", false),
+            ("mapping.users[1]", true),
+            ("mapping.users[2]", true),
+            ("mapping.users[3]", false),
+            ("mapping.users[4]", false),
+            ("mapping `restricted`", false),
+        ]
+    );
+
+    let admin = &explanation.rules[0].check;
+    assert_eq!(admin.description, "all of");
+    assert_eq!(
+        admin.checks[0].checks[0].description,
+        "attribute 1.1 is 0xAA, not returned"
+    );
+    assert_eq!(
+        admin.checks[1].description,
+        "realm is `example.com`, got `example.com`"
+    );
+
+    assert_eq!(
+        explanation.decision.reason,
+        "matched mapping.users[1] with priority 0"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_explains_attribute_values() -> Result<(), Error> {
+    let conf = mapping_config(None)?;
+
+    let mut user = User::new("alice");
+    user.attributes.push(Attribute {
+        vendor: 0,
+        subtype: 25,
+        data: b"dev".to_vec(),
+    });
+
+    let explanation = conf.explain_mapping(&user);
+    assert_eq!(
+        explanation.rules[4].check.description,
+        r#"attribute 0.25 is one of ["ops", "sre"], got "dev""#
+    );
+    assert_eq!(explanation.decision.mapping, None);
+    assert!(explanation
+        .to_string()
+        .ends_with("Account: none, the user is denied\n"));
+    Ok(())
+}
+
+fn dynamic_config() -> Result<Config, Error> {
+    let mut conf = mapping_config(None)?;
+    conf.mapping.users = toml::from_str::<Mappings>(
+        r#"
+        [[users]]
+        username = "%u"
+        gid = 2000
+        home = "/home/radius/%u"
+        shell = "/bin/bash"
+        dynamic = true
+        when = { realm = "example.com" }
+        "#,
+    )?
+    .users;
+    Ok(conf)
+}
+
+#[test]
+fn it_expands_dynamic_accounts() -> Result<(), Error> {
+    let conf = dynamic_config()?;
+    let user = conf
+        .map_user(&user("alice@example.com", &[]))
+        .ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.username, "alice@example.com");
+    assert_eq!(
+        user.mapping.home.as_deref(),
+        Some("/home/radius/alice@example.com")
+    );
+    assert_eq!(user.mapping.uid, None);
+    Ok(())
+}
+
+#[test]
+fn it_denies_invalid_dynamic_accounts() -> Result<(), Error> {
+    let mut conf = dynamic_config()?;
+    conf.accounts = Accounts::parse(
+        "bob@example.com:x:1000:1000::/home/bob:/bin/bash\n",
+        "",
+    );
+
+    let decision = conf.decide(&user("bob@example.com", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "matched mapping.users[0] with priority 0, but local user \
+         `bob@example.com` already exists"
+    );
+
+    let decision = conf.decide(&user("eve:0@example.com", &[]));
+    assert_eq!(decision.mapping, None);
+    Ok(())
+}
+
+#[test]
+fn it_expands_home_and_shell_templates() -> Result<(), Error> {
+    let mut conf = mapping_config(None)?;
+    conf.mapping.users = toml::from_str::<Mappings>(
+        r#"
+        [[users]]
+        username = "ops"
+        uid = 1020
+        gid = 1020
+        home = "/srv/%{attr:Class}/%l/%u"
+        shell = "/usr/local/bin/%{attr:Class}sh"
+        when = { attribute = "Class" }
+        "#,
+    )?
+    .users;
+
+    let mut radius = User::new("alice");
+    radius.attributes.push(Attribute {
+        vendor: 0,
+        subtype: 25,
+        data: b"sre".to_vec(),
+    });
+    let user = conf.map_user(&radius).ok_or(Error::UserNotFound)?;
+    assert_eq!(user.mapping.home.as_deref(), Some("/srv/sre/ops/alice"));
+    assert_eq!(user.mapping.shell.as_deref(), Some("/usr/local/bin/sresh"));
+
+    radius.attributes[0].data = b"../etc".to_vec();
+    let decision = conf.decide(&radius);
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "matched mapping.users[0] with priority 0, but `../etc` cannot be \
+         used in a path"
+    );
+    Ok(())
+}
+
+fn overrides_config() -> Result<Config, Error> {
+    let mut conf = mapping_config(None)?;
+    conf.mapping.users = toml::from_str::<Mappings>(
+        r#"
+        [[users]]
+        username = "ops"
+        uid = 1020
+        gid = 1020
+        home = "/home/ops"
+        shell = "/bin/bash"
+        when = { realm = "example.com" }
+
+        [users.overrides]
+        shell = { attribute = "1.10", allow = { in = ["/bin/zsh"] } }
+        groups = { attribute = "1.11", allow = { prefix = "ops-" } }
+        session_timeout = { attribute = "Session-Timeout", allow = { range = [60, 86400] } }
+        "#,
+    )?
+    .users;
+    conf.accounts = Accounts::parse("", "ops-db:x:2001:\nops-web:x:2002:\n");
+    Ok(conf)
+}
+
+fn with_text(mut user: User, vendor: u32, subtype: u8, text: &str) -> User {
+    user.attributes.push(Attribute {
+        vendor,
+        subtype,
+        data: text.as_bytes().to_vec(),
+    });
+    user
+}
+
+#[test]
+fn it_applies_attribute_overrides() -> Result<(), Error> {
+    let conf = overrides_config()?;
+
+    let radius = user("alice@example.com", &[]);
+    let mapped = conf.map_user(&radius).ok_or(Error::UserNotFound)?;
+    assert_eq!(mapped.mapping.shell.as_deref(), Some("/bin/bash"));
+    assert_eq!(mapped.account, Account::default());
+
+    let radius = with_text(radius, 1, 10, "/bin/zsh");
+    let radius = with_text(radius, 1, 11, "ops-db");
+    let radius = with_text(radius, 1, 11, "ops-web");
+    let mut radius = with_text(radius, 0, 27, "3600");
+    let mapped = conf.map_user(&radius).ok_or(Error::UserNotFound)?;
+    assert_eq!(mapped.mapping.shell.as_deref(), Some("/bin/zsh"));
+    let groups: Vec<_> = mapped.account.groups.iter().map(|g| g.gid).collect();
+    assert_eq!(groups, vec![2001, 2002]);
+    assert_eq!(mapped.account.session_timeout, Some(3600));
+
+    radius.attributes[3].data = vec![0, 0, 0, 10];
+    let decision = conf.decide(&radius);
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "matched mapping.users[0] with priority 0, but session timeout \
+         0x0000000A of attribute 0.27 is not allowed"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_denies_disallowed_overrides() -> Result<(), Error> {
+    let conf = overrides_config()?;
+
+    let radius = with_text(user("alice@example.com", &[]), 1, 10, "/bin/sh");
+    let decision = conf.decide(&radius);
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "matched mapping.users[0] with priority 0, but shell \"/bin/sh\" of \
+         attribute 1.10 is not allowed"
+    );
+
+    let radius = with_text(user("alice@example.com", &[]), 1, 11, "ops-x");
+    let decision = conf.decide(&radius);
+    assert_eq!(
+        decision.reason,
+        "matched mapping.users[0] with priority 0, but group \"ops-x\" of \
+         attribute 1.11 is not a local group"
+    );
+    Ok(())
+}
+
+fn command_config() -> Result<Config, Error> {
+    let mut conf = mapping_config(None)?;
+    let path = std::env::current_dir()?.join("../tests/mapping_command.sh");
+    conf.mapping.command = Some(MappingCommand {
+        path: path.canonicalize()?,
+        args: None,
+        timeout: Some(1),
+    });
+    Ok(conf)
+}
+
+#[test]
+fn it_runs_the_mapping_command() -> Result<(), Error> {
+    let conf = command_config()?;
+
+    let decision = conf.decide(&user("alice", &[]));
+    assert_eq!(decision.reason, "mapped by mapping.command");
+    let mapping = decision.mapping.ok_or(Error::UserNotFound)?;
+    assert_eq!(mapping.username, "ops");
+    assert_eq!(mapping.uid, Some(1020));
+
+    let decision = conf.decide(&user("bob@example.com", &[]));
+    assert_eq!(
+        decision.reason,
+        "mapping.command has no mapping, matched mapping.users[1] with \
+         priority 0"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_denies_when_the_mapping_command_fails() -> Result<(), Error> {
+    let conf = command_config()?;
+
+    let decision = conf.decide(&user("carol", &[]));
+    assert_eq!(decision.mapping, None);
+    assert!(decision
+        .reason
+        .starts_with("mapping.command failed, invalid output, unknown field"));
+
+    let decision = conf.decide(&user("dave", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "mapping.command failed, it did not exit within 1 seconds"
+    );
+
+    let decision = conf.decide(&user("frank", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "mapping.command returned uid 0 of `ops` is below 1000"
+    );
+
+    let decision = conf.decide(&user("eve", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "mapping.command failed, it exited with status 3"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_expands_mapping_command_templates() -> Result<(), Error> {
+    let conf = command_config()?;
+
+    let decision = conf.decide(&user("grace", &[]));
+    let mapping = decision.mapping.ok_or(Error::UserNotFound)?;
+    assert_eq!(mapping.home.as_deref(), Some("/home/grace"));
+
+    let decision = conf.decide(&user("mallory/x", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "mapping.command returned `mallory/x` cannot be used in a path"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_denies_unsafe_mapping_command_paths() -> Result<(), Error> {
+    let conf = command_config()?;
+
+    let decision = conf.decide(&user("oscar", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "mapping.command failed, invalid output, `/home/../root` contains \
+         `..`"
+    );
+
+    let decision = conf.decide(&user("peggy", &[]));
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "mapping.command failed, invalid output, \"/bin/ba\\u{7}sh\" \
+         contains a control character"
+    );
+    Ok(())
+}
+
+#[test]
+fn it_anchors_override_regexes() -> Result<(), Error> {
+    let mut conf = overrides_config()?;
+    conf.mapping.users[0].overrides = Some(toml::from_str(
+        r#"home = { attribute = "1.12", allow = { regex = "/home/[a-z]+" } }"#,
+    )?);
+
+    let radius = with_text(user("alice@example.com", &[]), 1, 12, "/home/ab");
+    let mapped = conf.map_user(&radius).ok_or(Error::UserNotFound)?;
+    assert_eq!(mapped.mapping.home.as_deref(), Some("/home/ab"));
+
+    let radius =
+        with_text(user("alice@example.com", &[]), 1, 12, "/tmp/x/home/ab");
+    assert_eq!(conf.decide(&radius).mapping, None);
+    Ok(())
+}
+
+#[test]
+fn it_denies_overrides_without_allow_list() -> Result<(), Error> {
+    let mut conf = overrides_config()?;
+    conf.mapping.users[0].overrides = Some(toml::from_str(
+        r#"groups = { attribute = "1.11", allow = {} }"#,
+    )?);
+
+    let radius = with_text(user("alice@example.com", &[]), 1, 11, "ops-db");
+    let decision = conf.decide(&radius);
+    assert_eq!(decision.mapping, None);
+    assert_eq!(
+        decision.reason,
+        "matched mapping.users[0] with priority 0, but group of attribute \
+         1.11 has no allow-list"
+    );
+    Ok(())
+}
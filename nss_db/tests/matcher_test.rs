@@ -0,0 +1,74 @@
+use nss_db::ValueMatch;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Value {
+    value: ValueMatch,
+}
+
+fn matcher(toml: &str) -> ValueMatch {
+    toml::from_str::<Value>(&format!("value = {}", toml))
+        .unwrap()
+        .value
+}
+
+#[test]
+fn it_matches_bytes() {
+    let m = matcher("[0x01, 0x02]");
+    assert!(m.matches(&[0x01, 0x02]));
+    assert!(!m.matches(&[0x01]));
+}
+
+#[test]
+fn it_matches_strings() {
+    let m = matcher(r#"{ string = "ops" }"#);
+    assert!(m.matches(b"ops"));
+    assert!(!m.matches(b"OPS"));
+
+    let m = matcher(r#"{ prefix = "grp=", ignore_case = true }"#);
+    assert!(m.matches(b"GRP=ops"));
+    assert!(!m.matches(b"grp"));
+    assert!(!m.matches(&[0xff, 0xfe, 0xfd, 0xfc]));
+
+    let m = matcher(r#"{ in = ["ops", "sre"] }"#);
+    assert!(m.matches(b"sre"));
+    assert!(!m.matches(b"dev"));
+}
+
+#[test]
+fn it_matches_regex() {
+    let m = matcher(r#"{ regex = "grp=(ops|sre)(;|$)" }"#);
+    assert!(m.matches(b"grp=ops;site=zrh"));
+    assert!(m.matches(b"site=zrh;grp=sre"));
+    assert!(!m.matches(b"grp=opsx;site=zrh"));
+}
+
+#[test]
+fn it_matches_ranges() {
+    let m = matcher("{ range = [10, 15] }");
+    assert!(m.matches(b"15"));
+    assert!(!m.matches(b"16"));
+    assert!(m.matches(&[0, 0, 0, 12]));
+    assert!(!m.matches(&[0, 0, 1, 12]));
+    assert!(!m.matches(b"level=12"));
+}
+
+#[test]
+fn it_combines_matchers() {
+    let m = matcher(r#"{ prefix = "grp=", regex = "site=zrh" }"#);
+    assert!(m.matches(b"grp=ops;site=zrh"));
+    assert!(!m.matches(b"grp=ops;site=gva"));
+}
+
+#[test]
+fn it_rejects_invalid_regex() {
+    assert!(toml::from_str::<Value>(r#"value = { regex = "(" }"#).is_err());
+}
+
+#[test]
+fn it_reports_unknown_matcher_fields() {
+    let (_, unknown) =
+        common::config::from_str::<Value>(r#"value = { prefx = "grp=" }"#)
+            .unwrap();
+    assert_eq!(unknown[0].key, "value.prefx");
+}
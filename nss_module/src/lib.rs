@@ -13,6 +13,7 @@ use libnss::shadow::{Shadow, ShadowHooks};
 use nss_db::setup_log;
 use nss_db::Config;
 use nss_db::Db;
+use nss_db::User;
 
 const SYSLOG_NAME: &str = "nss_radius_virtual";
 
@@ -30,75 +31,138 @@ impl PasswdHooks for VirtualPasswd {
             return Response::NotFound;
         }
 
-        setup_log(SYSLOG_NAME);
-
-        let config = match Config::system() {
-            Ok(config) => config,
-            Err(err) => {
-                error!("Cannot read system configuration: {}", err);
-                return Response::Unavail;
-            }
+        let (config, db) = match open() {
+            Some(opened) => opened,
+            None => return Response::Unavail,
         };
 
-        if config.debug() {
-            log::set_max_level(log::LevelFilter::Debug)
-        }
-
         debug!("Looking up user {}", name);
 
-        let db = match Db::with_config(&config) {
-            Ok(db) => db,
-            Err(err) => {
-                error!("Cannot read user database: {}", err);
-                return Response::Unavail;
-            }
-        };
+        let user = db.find_user(&name).ok();
 
-        let user = db.get_user(&name).ok();
+        let default = &config.mapping.default_user;
 
         match user {
             None => {
                 debug!(
                     "Didn't find any user for {}, \
                          fallling back to default user {}",
-                    name, config.mapping.default_user.username
+                    name, default.username
                 );
-                Response::Success(Passwd {
-                    name: config.mapping.default_user.username.clone(),
-                    passwd: "x".to_string(),
-                    uid: config.mapping.default_user.uid,
-                    gid: config.mapping.default_user.gid,
-                    gecos: "Radius default user".to_string(),
-                    dir: config.mapping.default_user.home.clone(),
-                    shell: config.mapping.default_user.shell.clone(),
-                })
+                match (default.uid, default.gid, &default.home) {
+                    (Some(uid), Some(gid), Some(home)) => {
+                        Response::Success(Passwd {
+                            name: default.username.clone(),
+                            passwd: "x".to_string(),
+                            uid,
+                            gid,
+                            gecos: "Radius default user".to_string(),
+                            dir: home.clone(),
+                            shell: default.shell.clone(),
+                        })
+                    }
+                    _ => {
+                        error!(
+                            "Default user {} is not resolved",
+                            default.username
+                        );
+                        Response::Unavail
+                    }
+                }
             }
             Some(user) => {
                 debug!(
                     "Found user {}, mapped to {}",
                     name, user.mapping.username
                 );
-                Response::Success(Passwd {
-                    name: user.mapping.username.clone(),
-                    passwd: "x".to_string(),
-                    uid: user.mapping.uid,
-                    gid: user.mapping.gid,
-                    gecos: format!(
-                        "Mapped RADIUS account {}->{}",
-                        name, user.mapping.username
-                    )
-                    .to_string(),
-                    dir: user.mapping.home,
-                    shell: config.mapping.default_user.shell.clone(),
-                })
+                mapped_passwd(user, &config)
             }
         }
     }
     fn get_all_entries() -> Response<Vec<Passwd>> {
         Response::Success(vec![])
     }
-    fn get_entry_by_uid(_uid: libc::uid_t) -> Response<Passwd> {
-        Response::NotFound
+    /// Only dynamic accounts are found by uid
+    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
+        if uid == 0 {
+            return Response::NotFound;
+        }
+
+        let root = nix::unistd::Uid::from_raw(0);
+        if nix::unistd::geteuid() != root {
+            return Response::NotFound;
+        }
+
+        let (config, db) = match open() {
+            Some(opened) => opened,
+            None => return Response::Unavail,
+        };
+
+        debug!("Looking up uid {}", uid);
+
+        match db.get_user_by_uid(uid) {
+            Ok(user) => {
+                debug!(
+                    "Found uid {}, mapped to {}",
+                    uid, user.mapping.username
+                );
+                mapped_passwd(user, &config)
+            }
+            Err(_) => Response::NotFound,
+        }
+    }
+}
+
+/// Read the system config and open the user database, errors are logged
+fn open() -> Option<(Config, Db)> {
+    setup_log(SYSLOG_NAME);
+
+    let config = match Config::system() {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Cannot read system configuration: {}", err);
+            return None;
+        }
+    };
+
+    if config.debug() {
+        log::set_max_level(log::LevelFilter::Debug)
+    }
+
+    match Db::with_config(&config) {
+        Ok(db) => Some((config, db)),
+        Err(err) => {
+            error!("Cannot read user database: {}", err);
+            None
+        }
+    }
+}
+
+/// Entry of a mapped user. Its shell is always the shell wrapper, which
+/// checks the cookie and the sessions before starting the mapping shell.
+fn mapped_passwd(user: User, config: &Config) -> Response<Passwd> {
+    let name = &user.radius.username;
+    let shell = &config.mapping.default_user.shell;
+    match (user.mapping.uid, user.mapping.gid, &user.mapping.home) {
+        (Some(uid), Some(gid), Some(home)) => Response::Success(Passwd {
+            name: user.mapping.username.clone(),
+            passwd: "x".to_string(),
+            uid,
+            gid,
+            gecos: format!(
+                "Mapped RADIUS account {}->{}",
+                name, user.mapping.username
+            ),
+            dir: home.clone(),
+            shell: shell.clone(),
+        }),
+        _ => {
+            error!(
+                "Mapping of {} to {} is not resolved",
+                name, user.mapping.username
+            );
+            Response::Unavail
+        }
     }
 }
 
@@ -121,7 +185,7 @@ impl ShadowHooks for VirtualShadow {
             _ => return Response::Unavail,
         };
 
-        let user = db.get_user(name).ok();
+        let user = db.find_user(&name).ok();
         match user {
             None => Response::NotFound,
             Some(user) => Response::Success(Shadow {
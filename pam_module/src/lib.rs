@@ -4,7 +4,10 @@ extern crate log;
 #[macro_use]
 extern crate pamsm;
 
-use pamsm::{Pam, PamError, PamFlag, PamLibExt, PamServiceModule};
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_int;
+
+use pamsm::{Pam, PamError, PamFlag, PamLibExt, PamMsgStyle, PamServiceModule};
 
 use nss_db::setup_log;
 use nss_db::Config;
@@ -12,9 +15,24 @@ use nss_db::Db;
 use radius::Client;
 use radius::Credentials;
 use radius::Error;
+use zeroize::Zeroizing;
 
 const SYSLOG_NAME: &str = "pam_radius_virtual";
 
+// From <security/_pam_types.h>
+const PAM_PRELIM_CHECK: i32 = 0x4000;
+const PAM_OLDAUTHTOK: c_int = 7;
+const PAM_SUCCESS: c_int = 0;
+
+// pamsm can read PAM_OLDAUTHTOK but not set it
+extern "C" {
+    fn pam_set_item(
+        pamh: *mut c_void,
+        item_type: c_int,
+        item: *const c_void,
+    ) -> c_int;
+}
+
 struct PamRadius;
 
 impl PamServiceModule for PamRadius {
@@ -28,7 +46,11 @@ impl PamServiceModule for PamRadius {
         let config = match Config::system() {
             Ok(config) => config,
             Err(err) => {
-                error!("Cannot read configuration file: {}", err);
+                error!(
+                    "Cannot read configuration file {}: {}",
+                    Config::system_path(),
+                    err
+                );
                 return PamError::SERVICE_ERR;
             }
         };
@@ -105,7 +127,10 @@ impl PamServiceModule for PamRadius {
 
         let radius_user = match res {
             Ok(user) => user,
-            Err(Error::AuthReject) => return PamError::AUTH_ERR,
+            Err(Error::AuthReject(response)) => {
+                info!("Authentication rejected for {}: {}", username, response);
+                return PamError::AUTH_ERR;
+            }
             Err(err) => {
                 error!("Radius error: {}", err);
                 return PamError::SERVICE_ERR;
@@ -156,7 +181,11 @@ impl PamServiceModule for PamRadius {
         let config = match config {
             Ok(config) => config,
             Err(err) => {
-                error!("Cannot read configuration file: {}", err);
+                error!(
+                    "Cannot read configuration file {}: {}",
+                    Config::system_path(),
+                    err
+                );
                 return PamError::SERVICE_ERR;
             }
         };
@@ -193,6 +222,218 @@ impl PamServiceModule for PamRadius {
             _ => PamError::AUTH_ERR,
         }
     }
+
+    fn chauthtok(pamh: Pam, flags: PamFlag, args: Vec<String>) -> PamError {
+        setup_log(SYSLOG_NAME);
+
+        let config = match Config::system() {
+            Ok(config) => config,
+            Err(err) => {
+                error!(
+                    "Cannot read configuration file {}: {}",
+                    Config::system_path(),
+                    err
+                );
+                return PamError::SERVICE_ERR;
+            }
+        };
+
+        if config.debug() {
+            log::set_max_level(log::LevelFilter::Debug)
+        }
+
+        let username = match pamh.get_user(None) {
+            Ok(Some(u)) => u,
+            Ok(None) => {
+                error!("Cannot get username");
+                return PamError::USER_UNKNOWN;
+            }
+            Err(e) => return e,
+        };
+
+        let username = match username.to_str() {
+            Ok(u) => u.to_string(),
+            _ => {
+                error!("Cannot convert username to string");
+                return PamError::USER_UNKNOWN;
+            }
+        };
+
+        if username == "root" {
+            return PamError::USER_UNKNOWN;
+        }
+
+        let client = match Client::with_config(&config.radius) {
+            Ok(client) => client,
+            Err(err) => {
+                error!("Cannot create radius client: {}", err);
+                return PamError::SERVICE_ERR;
+            }
+        };
+
+        if flags as i32 & PAM_PRELIM_CHECK != 0 {
+            return prelim_check(&pamh, &client, &username);
+        }
+
+        let old_pass = match old_password(&pamh) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let use_authtok = args.iter().any(|arg| arg == "use_authtok");
+
+        let new_pass = match new_password(&pamh, use_authtok) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Cannot get new password for {}", username);
+                return e;
+            }
+        };
+
+        let cred =
+            Credentials::with_username_password(&username[..], &old_pass[..]);
+
+        match client.change_password(&cred, &new_pass) {
+            Ok(()) => {
+                debug!("Changed password for {}", username);
+            }
+            Err(Error::AuthReject(response)) => {
+                error!(
+                    "Password change rejected for {}: {}",
+                    username, response
+                );
+                return PamError::AUTHTOK_ERR;
+            }
+            Err(err) => {
+                error!("Radius error: {}", err);
+                return PamError::SERVICE_ERR;
+            }
+        }
+
+        // The modules stacked after this one read it with use_authtok
+        if let Err(err) = set_authtok(&pamh, &new_pass) {
+            warn!("Cannot set new password for next modules: {}", err);
+        }
+
+        PamError::SUCCESS
+    }
+}
+
+/// Read the old password and check it with the server, before the next
+/// modules prompt for the new password. It is kept in PAM_OLDAUTHTOK for the
+/// update phase.
+fn prelim_check(pamh: &Pam, client: &Client, username: &str) -> PamError {
+    let old_pass = match old_password(pamh) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    let cred = Credentials::with_username_password(username, &old_pass[..]);
+
+    match client.authenticate(&cred) {
+        Ok(_) => {}
+        // An expired password is only accepted for the change
+        Err(Error::AuthReject(response)) if response.password_expired => {}
+        Err(Error::AuthReject(response)) => {
+            error!("Current password rejected for {}: {}", username, response);
+            return PamError::AUTHTOK_ERR;
+        }
+        Err(err) => {
+            error!("Cannot reach RADIUS server: {}", err);
+            return PamError::TRY_AGAIN;
+        }
+    }
+
+    match set_oldauthtok(pamh, &old_pass) {
+        Ok(()) => PamError::SUCCESS,
+        Err(e) => {
+            error!("Cannot keep current password of {}", username);
+            e
+        }
+    }
+}
+
+/// Old password set by a previous module or the preliminary check, or
+/// prompted
+fn old_password(pamh: &Pam) -> Result<Zeroizing<String>, PamError> {
+    match pamh.get_cached_oldauthtok()? {
+        Some(p) => to_password(p),
+        None => prompt_password(pamh, "Current RADIUS password: "),
+    }
+}
+
+/// New password set by a previous module, or prompted when `use_authtok` is
+/// not given
+fn new_password(
+    pamh: &Pam,
+    use_authtok: bool,
+) -> Result<Zeroizing<String>, PamError> {
+    if let Some(p) = pamh.get_cached_authtok()? {
+        return to_password(p);
+    }
+
+    if use_authtok {
+        return Err(PamError::AUTHTOK_ERR);
+    }
+
+    let new_pass = prompt_password(pamh, "New RADIUS password: ")?;
+    let retyped_pass = prompt_password(pamh, "Retype new RADIUS password: ")?;
+
+    if *new_pass != *retyped_pass {
+        error!("New passwords do not match");
+        return Err(PamError::AUTHTOK_ERR);
+    }
+
+    Ok(new_pass)
+}
+
+fn set_authtok(pamh: &Pam, password: &str) -> Result<(), PamError> {
+    let authtok = CString::new(password).map_err(|_| PamError::AUTHTOK_ERR)?;
+    let res = pamh.set_authtok(&authtok);
+    Zeroizing::new(authtok.into_bytes());
+    res
+}
+
+fn set_oldauthtok(pamh: &Pam, password: &str) -> Result<(), PamError> {
+    let authtok = CString::new(password).map_err(|_| PamError::AUTHTOK_ERR)?;
+    // Pam is a transparent wrapper of the pam_handle_t pointer
+    debug_assert_eq!(
+        std::mem::size_of::<Pam>(),
+        std::mem::size_of::<*mut c_void>()
+    );
+    let res = unsafe {
+        let handle: *mut c_void = std::mem::transmute_copy(pamh);
+        pam_set_item(handle, PAM_OLDAUTHTOK, authtok.as_ptr() as *const c_void)
+    };
+    Zeroizing::new(authtok.into_bytes());
+    if res == PAM_SUCCESS {
+        Ok(())
+    } else {
+        Err(PamError::AUTHTOK_ERR)
+    }
+}
+
+fn prompt_password(
+    pamh: &Pam,
+    prompt: &str,
+) -> Result<Zeroizing<String>, PamError> {
+    match pamh.conv(Some(prompt), PamMsgStyle::PROMPT_ECHO_OFF) {
+        Ok(Some(p)) => to_password(p),
+        Ok(None) => {
+            error!("Cannot get password");
+            Err(PamError::AUTHTOK_ERR)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn to_password(p: &CStr) -> Result<Zeroizing<String>, PamError> {
+    p.to_str()
+        .map(|p| Zeroizing::new(p.to_string()))
+        .map_err(|_| {
+            error!("Cannot convert password to string");
+            PamError::AUTHTOK_ERR
+        })
 }
 
 pam_module!(PamRadius);
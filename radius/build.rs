@@ -30,14 +30,15 @@ const FILES_WINDOWS: &'static [&'static str] = &["src/utils/os_win32.c"];
 const FILES_UNIX: &'static [&'static str] = &["src/utils/os_unix.c"];
 
 const FILES_COMMON: &'static [&'static str] = &[
-    "src/utils/wpa_debug.c",
     "src/utils/common.c",
     "src/utils/eloop.c",
     "src/utils/ip_addr.c",
     "src/utils/wpabuf.c",
     "src/crypto/crypto_openssl.c",
+    "src/crypto/ms_funcs.c",
     "src/radius/radius_client.c",
     "src/radius/radius.c",
+    "src/radius/radius_das.c",
 ];
 
 fn main() {
@@ -47,6 +48,8 @@ fn main() {
     let target = env::var("TARGET").unwrap();
     let windows = target.contains("windows");
 
+    check_hostap();
+
     // We cannot use cfg! here because build.rs target is host machine
     let (config, files) = if windows {
         (CONFIG_WINDOWS, FILES_WINDOWS)
@@ -94,6 +97,7 @@ fn main() {
         .flag_if_supported("-Wno-unused-parameter")
         .flag("-includewin_patch.h")
         .file("src/bindings.c")
+        .file("src/debug.c")
         .include("src")
         .include("../hostap/src")
         .include("../hostap/src/utils")
@@ -101,6 +105,28 @@ fn main() {
         .compile("hostap");
 }
 
+/// Binding the client to an interface needs `force_client_dev` in the RADIUS
+/// client of hostap, fail early instead of on a C error if the submodule is
+/// older.
+fn check_hostap() {
+    let header = "../hostap/src/radius/radius_client.h";
+    println!("cargo:rerun-if-changed={}", header);
+
+    let content = std::fs::read_to_string(header).unwrap_or_else(|err| {
+        panic!(
+            "Cannot read {}, is the hostap submodule cloned? {}",
+            header, err
+        )
+    });
+
+    if !content.contains("force_client_dev") {
+        panic!(
+            "hostap submodule is too old, update it to a revision with \
+             `force_client_dev` in its RADIUS client"
+        );
+    }
+}
+
 fn load_config(build: &mut cc::Build, config: &[(&str, &str)]) {
     for c in config.iter() {
         build.define(c.0, c.1);
@@ -1,9 +1,31 @@
 #include "bindings.h"
+#include "crypto/ms_funcs.h"
 #include "ip_addr.h"
 
+/*
+ * Microsoft vendor attributes used for password change (RFC 2548), not
+ * defined by hostap.
+ */
+#define MS_CHAP_ERROR 2
+#define MS_CHAP_NT_ENC_PW 6
+#define MS_CHAP2_CPW 27
+
+#define MS_CHAP_ERROR_PASSWD_EXPIRED 648
+
+#define MS_CHAP_NT_ENC_PW_CODE 6
+#define MS_CHAP2_CPW_CODE 7
+
+#define MS_CHAP_CHALLENGE_LEN 16
+#define MS_CHAP2_CPW_LEN 68
+#define MS_CHAP_ENC_PW_LEN 516
+#define MS_CHAP_ENC_PW_CHUNK 243
+
 struct server_state {
   struct hostapd_radius_server radius_server;
   int timeout;
+  int force_client_addr;
+  struct hostapd_ip_addr client_addr;
+  char* client_dev;
 };
 
 struct vendor_attr {
@@ -20,16 +42,37 @@ struct context {
   struct hostapd_radius_servers conf;
   struct radius_client_data* radius;
 
+  /* Requested attributes, data is not used */
   struct vendor_attr* vendor_attrs;
   int vendor_attrs_count;
 
+  /* Requested attributes found in the last Access-Accept */
+  struct vendor_attr* values;
+  int values_count;
+
   const char* username;
   const char* password;
+  const char* new_password;
 
   int did_timeout;
   int result_code;
+  struct result_info result;
 
   u8 radius_identifier;
+
+  rc_trace_cb trace_cb;
+  void* trace_data;
+};
+
+struct das_context {
+  struct radius_das_data* das;
+  struct hostapd_ip_addr client_addr;
+  rc_das_cb cb;
+  void* data;
+
+  /* Requested attributes, copied from CoA-Request, data is not used */
+  struct vendor_attr* vendor_attrs;
+  int vendor_attrs_count;
 };
 
 enum { RC_ACCEPT = 0, RC_REJECT, RC_ERROR, RC_NO_SERV, RC_SERV_TIMEOUT };
@@ -42,6 +85,17 @@ void rc_free(void* ptr) {
   os_free(ptr);
 }
 
+/*
+ * Wipe memory holding secrets, volatile ensures the writes are not optimized
+ * away before the memory is freed or goes out of scope.
+ */
+static void rc_memzero(void* ptr, size_t len) {
+  volatile u8* p = ptr;
+  while (len--) {
+    *p++ = 0;
+  }
+}
+
 static int init_lock = 0;
 
 static inline int rc_init_lock() {
@@ -85,18 +139,6 @@ void rc_deinit(void) {
   set_rc_init_lock(0);
 }
 
-static void logger_cb(void* ctx,
-                      const u8* addr,
-                      unsigned int module,
-                      int level,
-                      const char* txt,
-                      size_t len) {
-  if (addr && *addr) {
-    wpa_printf(MSG_DEBUG, "STA " MACSTR ": %s", MAC2STR(addr), txt);
-  } else
-    wpa_printf(MSG_DEBUG, "%s", txt);
-}
-
 rc_ctx rc_create_context(void) {
   rc_ctx ctx = os_zalloc(sizeof(*ctx));
   if (!ctx) {
@@ -108,47 +150,110 @@ rc_ctx rc_create_context(void) {
   return ctx;
 }
 
+static void clear_values(struct vendor_attr* values, int* count) {
+  for (int i = 0; i < *count; i++) {
+    os_free(values[i].data);
+  }
+  *count = 0;
+}
+
+/* Append a value, data is owned by the list on success */
+static int add_value(struct vendor_attr** values,
+                     int* count,
+                     u32 vendor,
+                     u8 subtype,
+                     u8* data,
+                     size_t len) {
+  struct vendor_attr* list =
+      os_realloc(*values, (*count + 1) * sizeof(struct vendor_attr));
+
+  if (!list) {
+    return -1;
+  }
+  *values = list;
+
+  struct vendor_attr* value = list + (*count)++;
+  value->vendor = vendor;
+  value->subtype = subtype;
+  value->data = data;
+  value->len = len;
+
+  return 0;
+}
+
+/*
+ * Copy a requested attribute from a message, every occurrence of standard
+ * attributes (vendor 0) is copied, only the first one of vendor attributes.
+ */
+static void copy_attribute(struct vendor_attr** values,
+                           int* count,
+                           struct radius_msg* msg,
+                           u32 vendor,
+                           u8 subtype) {
+  u8* data;
+  size_t len;
+
+  if (vendor == 0) {
+    u8* buf = NULL;
+    while (radius_msg_get_attr_ptr(msg, subtype, &buf, &len, buf) == 0) {
+      data = os_malloc(len > 0 ? len : 1);
+      if (!data) {
+        return;
+      }
+      os_memcpy(data, buf, len);
+      if (add_value(values, count, vendor, subtype, data, len)) {
+        os_free(data);
+        return;
+      }
+    }
+  } else {
+    data = radius_msg_get_vendor_attr(msg, vendor, subtype, &len);
+    if (data && add_value(values, count, vendor, subtype, data, len)) {
+      os_free(data);
+    }
+  }
+}
+
 void rc_destroy_context(rc_ctx ctx) {
   for (int i = 0; i < ctx->server_count; i++) {
     struct server_state* state = ctx->servers + i;
+    rc_memzero(state->radius_server.shared_secret,
+               state->radius_server.shared_secret_len);
     os_free(state->radius_server.shared_secret);
+    os_free(state->client_dev);
   }
 
-  for (int i = 0; i < ctx->vendor_attrs_count; i++) {
-    struct vendor_attr* attr = ctx->vendor_attrs + i;
-    os_free(attr->data);
-  }
+  clear_values(ctx->values, &ctx->values_count);
 
   os_free(ctx->servers);
   os_free(ctx->vendor_attrs);
+  os_free(ctx->values);
+  os_free(ctx->result.reply_message);
   os_free(ctx);
 }
 
 void rc_enable_debug(rc_ctx ctx) {
-  hostapd_logger_register_cb(logger_cb);
   ctx->conf.msg_dumps = 1;
-  wpa_debug_level = MSG_DEBUG;
 }
 
-int rc_add_attribute(rc_ctx ctx, u32 vendor, u8 subtype) {
-  int count = ctx->vendor_attrs_count;
-
-  ctx->vendor_attrs =
-      os_realloc(ctx->vendor_attrs, (count + 1) * sizeof(struct vendor_attr));
+void rc_set_trace(rc_ctx ctx, rc_trace_cb cb, void* data) {
+  ctx->trace_cb = cb;
+  ctx->trace_data = data;
+}
 
-  if (!ctx->vendor_attrs) {
-    return -1;
+static void trace_msg(rc_ctx ctx, int sent, struct radius_msg* msg) {
+  if (!ctx->trace_cb) {
+    return;
   }
-  ctx->vendor_attrs_count++;
-
-  struct vendor_attr* attr = ctx->vendor_attrs + count;
-
-  os_memset(attr, 0, sizeof(*attr));
 
-  attr->vendor = vendor;
-  attr->subtype = subtype;
+  struct wpabuf* buf = radius_msg_get_buf(msg);
+  ctx->trace_cb(ctx->trace_data, ctx->current_server, sent, wpabuf_head(buf),
+                wpabuf_len(buf));
+}
 
-  return 0;
+int rc_add_attribute(rc_ctx ctx, u32 vendor, u8 subtype) {
+  return add_value(&ctx->vendor_attrs, &ctx->vendor_attrs_count, vendor,
+                   subtype, NULL, 0);
 }
 
 int rc_add_server(rc_ctx ctx,
@@ -156,7 +261,10 @@ int rc_add_server(rc_ctx ctx,
                   const u8* ip,
                   int ipv6,
                   u16 port,
-                  u16 timeout) {
+                  u16 timeout,
+                  const u8* source_ip,
+                  int source_ipv6,
+                  const char* interface) {
   int count = ctx->server_count;
 
   ctx->servers =
@@ -169,6 +277,7 @@ int rc_add_server(rc_ctx ctx,
   ctx->server_count++;
 
   struct server_state* state = ctx->servers + count;
+  os_memset(state, 0, sizeof(*state));
   state->timeout = timeout;
   struct hostapd_radius_server* srv = &state->radius_server;
 
@@ -196,6 +305,25 @@ int rc_add_server(rc_ctx ctx,
 
   srv->port = port;
 
+  if (source_ip) {
+    state->force_client_addr = 1;
+    if (source_ipv6) {
+      state->client_addr.af = AF_INET6;
+      os_memcpy(&state->client_addr.u.v6, source_ip, sizeof(struct in6_addr));
+    } else {
+      state->client_addr.af = AF_INET;
+      os_memcpy(&state->client_addr.u.v4, source_ip, sizeof(struct in_addr));
+    }
+  }
+
+  if (interface) {
+    state->client_dev = os_strdup(interface);
+
+    if (!state->client_dev) {
+      return -1;
+    }
+  }
+
   return 0;
 }
 
@@ -206,6 +334,34 @@ static void server_timeout(void* eloop_ctx, void* timeout_ctx) {
   eloop_terminate();
 }
 
+/*
+ * Code of the MS-CHAP-Error attribute, "E=<code> R=..." after the
+ * identifier, 0 if there is none.
+ */
+static int ms_chap_error(struct radius_msg* msg) {
+  size_t len;
+  int error = 0;
+
+  u8* data = radius_msg_get_vendor_attr(msg, RADIUS_VENDOR_ID_MICROSOFT,
+                                        MS_CHAP_ERROR, &len);
+  if (!data) {
+    return 0;
+  }
+
+  char* text = len > 1 ? dup_binstr(data + 1, len - 1) : NULL;
+  os_free(data);
+
+  if (text) {
+    char* code = os_strstr(text, "E=");
+    if (code) {
+      error = atoi(code + 2);
+    }
+    os_free(text);
+  }
+
+  return error;
+}
+
 static RadiusRxResult receive_auth(struct radius_msg* msg,
                                    struct radius_msg* req,
                                    const u8* shared_secret,
@@ -213,10 +369,23 @@ static RadiusRxResult receive_auth(struct radius_msg* msg,
                                    void* data) {
   rc_ctx ctx = data;
 
+  trace_msg(ctx, 0, msg);
+
+  /* A forged response is dropped, the request is retransmitted until a valid
+   * response arrives or the server times out */
+  if (radius_msg_verify(msg, shared_secret, shared_secret_len, req, 0)) {
+    wpa_printf(MSG_ERROR, "Invalid RADIUS response authenticator");
+    ctx->result.error = RC_ERR_BAD_AUTHENTICATOR;
+    return RADIUS_RX_INVALID_AUTHENTICATOR;
+  }
+
   eloop_cancel_timeout(server_timeout, ctx, NULL);
 
   struct radius_hdr* hdr = radius_msg_get_hdr(msg);
 
+  ctx->result.code = hdr->code;
+  ctx->result.error = 0;
+
   switch (hdr->code) {
     case RADIUS_CODE_ACCESS_ACCEPT:
       ctx->result_code = RC_ACCEPT;
@@ -226,6 +395,7 @@ static RadiusRxResult receive_auth(struct radius_msg* msg,
       break;
     default:
       ctx->result_code = RC_ERROR;
+      ctx->result.error = RC_ERR_UNEXPECTED_CODE;
       break;
   }
   wpa_printf(MSG_DEBUG, "Received RADIUS Authentication message; code=%d",
@@ -233,12 +403,27 @@ static RadiusRxResult receive_auth(struct radius_msg* msg,
 
   eloop_terminate();
 
+  u8* reply;
+  size_t reply_len;
+
+  if (radius_msg_get_attr_ptr(msg, RADIUS_ATTR_REPLY_MESSAGE, &reply,
+                              &reply_len, NULL) == 0) {
+    os_free(ctx->result.reply_message);
+    ctx->result.reply_message = dup_binstr(reply, reply_len);
+  }
+
+  if (ctx->result_code == RC_REJECT) {
+    ctx->result.password_expired =
+        ms_chap_error(msg) == MS_CHAP_ERROR_PASSWD_EXPIRED;
+  }
+
   if (ctx->result_code == RC_ACCEPT) {
+    clear_values(ctx->values, &ctx->values_count);
+
     for (int i = 0; i < ctx->vendor_attrs_count; i++) {
       struct vendor_attr* attr = ctx->vendor_attrs + i;
-      os_free(attr->data);
-      attr->data = radius_msg_get_vendor_attr(msg, attr->vendor, attr->subtype,
-                                              &attr->len);
+      copy_attribute(&ctx->values, &ctx->values_count, msg, attr->vendor,
+                     attr->subtype);
 
       wpa_printf(MSG_DEBUG, "Copied RADIUS attribute; vendor=%d subtype=%d",
                  attr->vendor, attr->subtype);
@@ -248,6 +433,169 @@ static RadiusRxResult receive_auth(struct radius_msg* msg,
   return RADIUS_RX_PROCESSED;
 }
 
+static int add_ms_attr(struct radius_msg* msg,
+                       u8 type,
+                       const u8* data,
+                       size_t len) {
+  u8 buf[RADIUS_MAX_ATTR_LEN];
+  struct radius_attr_vendor* vhdr;
+
+  if (4 + sizeof(*vhdr) + len > sizeof(buf)) {
+    return 0;
+  }
+
+  WPA_PUT_BE32(buf, RADIUS_VENDOR_ID_MICROSOFT);
+  vhdr = (struct radius_attr_vendor*)(buf + 4);
+  vhdr->vendor_type = type;
+  vhdr->vendor_length = sizeof(*vhdr) + len;
+  os_memcpy(vhdr + 1, data, len);
+
+  return radius_msg_add_attr(msg, RADIUS_ATTR_VENDOR_SPECIFIC, buf,
+                             4 + sizeof(*vhdr) + len) != NULL;
+}
+
+/*
+ * Add the MS-CHAP-Challenge, MS-CHAP2-CPW and MS-CHAP-NT-Enc-PW attributes
+ * required by a MS-CHAPv2 password change (RFC 2548).
+ */
+static int add_change_password(rc_ctx ctx, struct radius_msg* msg) {
+  u8 auth_challenge[MS_CHAP_CHALLENGE_LEN];
+  u8 peer_challenge[MS_CHAP_CHALLENGE_LEN];
+  u8 cpw[MS_CHAP2_CPW_LEN];
+  u8 enc_pw[MS_CHAP_ENC_PW_LEN];
+  u8 chunk[4 + MS_CHAP_ENC_PW_CHUNK];
+  u16 seq = 1;
+  int res = -1;
+
+  const u8* username = (const u8*)ctx->username;
+  const u8* old_pw = (const u8*)ctx->password;
+  const u8* new_pw = (const u8*)ctx->new_password;
+  size_t username_len = strlen(ctx->username);
+  size_t old_pw_len = strlen(ctx->password);
+  size_t new_pw_len = strlen(ctx->new_password);
+
+  os_memset(cpw, 0, sizeof(cpw));
+  os_memset(enc_pw, 0, sizeof(enc_pw));
+  os_memset(chunk, 0, sizeof(chunk));
+
+  if (os_get_random(auth_challenge, sizeof(auth_challenge)) ||
+      os_get_random(peer_challenge, sizeof(peer_challenge))) {
+    goto out;
+  }
+
+  if (!add_ms_attr(msg, RADIUS_VENDOR_ATTR_MS_CHAP_CHALLENGE, auth_challenge,
+                   sizeof(auth_challenge))) {
+    goto out;
+  }
+
+  cpw[0] = MS_CHAP2_CPW_CODE;
+  cpw[1] = ctx->radius_identifier;
+
+  if (old_nt_password_hash_encrypted_with_new_nt_password_hash(
+          new_pw, new_pw_len, old_pw, old_pw_len, cpw + 2)) {
+    goto out;
+  }
+
+  os_memcpy(cpw + 18, peer_challenge, sizeof(peer_challenge));
+
+  if (generate_nt_response(auth_challenge, peer_challenge, username,
+                           username_len, new_pw, new_pw_len, cpw + 42)) {
+    goto out;
+  }
+
+  if (!add_ms_attr(msg, MS_CHAP2_CPW, cpw, sizeof(cpw))) {
+    goto out;
+  }
+
+  if (new_password_encrypted_with_old_nt_password_hash(
+          new_pw, new_pw_len, old_pw, old_pw_len, enc_pw)) {
+    goto out;
+  }
+
+  for (size_t pos = 0; pos < sizeof(enc_pw); pos += MS_CHAP_ENC_PW_CHUNK) {
+    size_t len = sizeof(enc_pw) - pos;
+    if (len > MS_CHAP_ENC_PW_CHUNK) {
+      len = MS_CHAP_ENC_PW_CHUNK;
+    }
+
+    chunk[0] = MS_CHAP_NT_ENC_PW_CODE;
+    chunk[1] = ctx->radius_identifier;
+    WPA_PUT_BE16(chunk + 2, seq++);
+    os_memcpy(chunk + 4, enc_pw + pos, len);
+
+    if (!add_ms_attr(msg, MS_CHAP_NT_ENC_PW, chunk, 4 + len)) {
+      goto out;
+    }
+  }
+
+  res = 0;
+
+out:
+  rc_memzero(cpw, sizeof(cpw));
+  rc_memzero(enc_pw, sizeof(enc_pw));
+  rc_memzero(chunk, sizeof(chunk));
+  return res;
+}
+
+/*
+ * Address of the bound interface used to reach the server, read with
+ * getsockname() from a socket bound to the interface and connected to the
+ * server. Returns -1 if it cannot be found.
+ */
+static int interface_addr(struct server_state* state,
+                          struct hostapd_ip_addr* addr) {
+#ifdef SO_BINDTODEVICE
+  struct hostapd_radius_server* srv = &state->radius_server;
+  struct sockaddr_storage server, local;
+  socklen_t server_len, local_len = sizeof(local);
+  int res = -1;
+
+  os_memset(&server, 0, sizeof(server));
+  if (srv->addr.af == AF_INET6) {
+    struct sockaddr_in6* sin6 = (struct sockaddr_in6*)&server;
+    sin6->sin6_family = AF_INET6;
+    sin6->sin6_port = htons(srv->port);
+    os_memcpy(&sin6->sin6_addr, &srv->addr.u.v6, sizeof(struct in6_addr));
+    server_len = sizeof(*sin6);
+  } else {
+    struct sockaddr_in* sin = (struct sockaddr_in*)&server;
+    sin->sin_family = AF_INET;
+    sin->sin_port = htons(srv->port);
+    os_memcpy(&sin->sin_addr, &srv->addr.u.v4, sizeof(struct in_addr));
+    server_len = sizeof(*sin);
+  }
+
+  int sock = socket(server.ss_family, SOCK_DGRAM, 0);
+  if (sock < 0) {
+    return -1;
+  }
+
+  if (setsockopt(sock, SOL_SOCKET, SO_BINDTODEVICE, state->client_dev,
+                 os_strlen(state->client_dev) + 1) < 0 ||
+      connect(sock, (struct sockaddr*)&server, server_len) < 0 ||
+      getsockname(sock, (struct sockaddr*)&local, &local_len) < 0) {
+    goto out;
+  }
+
+  if (local.ss_family == AF_INET6) {
+    addr->af = AF_INET6;
+    os_memcpy(&addr->u.v6, &((struct sockaddr_in6*)&local)->sin6_addr,
+              sizeof(struct in6_addr));
+  } else {
+    addr->af = AF_INET;
+    os_memcpy(&addr->u.v4, &((struct sockaddr_in*)&local)->sin_addr,
+              sizeof(struct in_addr));
+  }
+  res = 0;
+
+out:
+  close(sock);
+  return res;
+#else
+  return -1;
+#endif
+}
+
 static void send_auth(void* eloop_ctx, void* timeout_ctx) {
   rc_ctx ctx = eloop_ctx;
   struct radius_msg* msg;
@@ -258,7 +606,7 @@ static void send_auth(void* eloop_ctx, void* timeout_ctx) {
   msg = radius_msg_new(RADIUS_CODE_ACCESS_REQUEST, ctx->radius_identifier);
   if (msg == NULL) {
     wpa_printf(MSG_ERROR, "Could not create net RADIUS packet");
-    return;
+    goto fail;
   }
 
   radius_msg_make_authenticator(msg);
@@ -266,30 +614,67 @@ static void send_auth(void* eloop_ctx, void* timeout_ctx) {
   if (!radius_msg_add_attr(msg, RADIUS_ATTR_USER_NAME, (u8*)ctx->username,
                            strlen(ctx->username))) {
     wpa_printf(MSG_ERROR, "Could not add User-Name");
-    radius_msg_free(msg);
-    return;
+    goto fail;
   }
 
-  if (!radius_msg_add_attr_user_password(
-          msg, (u8*)ctx->password, strlen(ctx->password),
-          ctx->conf.auth_server->shared_secret,
-          ctx->conf.auth_server->shared_secret_len)) {
+  if (ctx->new_password) {
+    if (add_change_password(ctx, msg)) {
+      wpa_printf(MSG_ERROR, "Could not add MS-CHAPv2 password change");
+      goto fail;
+    }
+  } else if (!radius_msg_add_attr_user_password(
+                 msg, (u8*)ctx->password, strlen(ctx->password),
+                 ctx->conf.auth_server->shared_secret,
+                 ctx->conf.auth_server->shared_secret_len)) {
     wpa_printf(MSG_ERROR, "Could not add User-Password");
-    radius_msg_free(msg);
-    return;
+    goto fail;
   }
 
-  /*
-  if (!radius_msg_add_attr(msg, RADIUS_ATTR_NAS_IP_ADDRESS,
-                           (u8*)&ctx->own_ip_addr, 4)) {
-    printf("Could not add NAS-IP-Address\n");
-    radius_msg_free(msg);
-    return;
+  struct server_state* srv = ctx->servers + ctx->current_server;
+
+  /* The source address, or the address of the bound interface */
+  struct hostapd_ip_addr nas_addr = srv->client_addr;
+  int has_nas_addr = srv->force_client_addr;
+
+  if (!has_nas_addr && srv->client_dev) {
+    has_nas_addr = interface_addr(srv, &nas_addr) == 0;
+    if (!has_nas_addr) {
+      wpa_printf(MSG_WARNING, "Could not get address of interface %s",
+                 srv->client_dev);
+    }
+  }
+
+  if (has_nas_addr && nas_addr.af == AF_INET &&
+      !radius_msg_add_attr(msg, RADIUS_ATTR_NAS_IP_ADDRESS,
+                           (u8*)&nas_addr.u.v4, 4)) {
+    wpa_printf(MSG_ERROR, "Could not add NAS-IP-Address");
+    goto fail;
+  }
+
+  if (has_nas_addr && nas_addr.af == AF_INET6 &&
+      !radius_msg_add_attr(msg, RADIUS_ATTR_NAS_IPV6_ADDRESS,
+                           (u8*)&nas_addr.u.v6, 16)) {
+    wpa_printf(MSG_ERROR, "Could not add NAS-IPv6-Address");
+    goto fail;
   }
-  */
 
-  if (radius_client_send(ctx->radius, msg, RADIUS_AUTH, NULL) < 0)
-    radius_msg_free(msg);
+  if (radius_client_send(ctx->radius, msg, RADIUS_AUTH, NULL) < 0) {
+    wpa_printf(MSG_ERROR, "Could not send RADIUS packet");
+    goto fail;
+  }
+
+  /* The message is finalized by radius_client_send and kept by the client
+   * for retransmission */
+  trace_msg(ctx, 1, msg);
+
+  return;
+
+fail:
+  radius_msg_free(msg);
+  eloop_cancel_timeout(server_timeout, ctx, NULL);
+  ctx->result_code = RC_ERROR;
+  ctx->result.error = RC_ERR_REQUEST;
+  eloop_terminate();
 }
 
 static int try_auth(rc_ctx ctx) {
@@ -300,15 +685,20 @@ static int try_auth(rc_ctx ctx) {
   ctx->conf.auth_server = &srv->radius_server;
   ctx->conf.auth_servers = &srv->radius_server;
   ctx->conf.num_auth_servers = 1;
+  ctx->conf.force_client_addr = srv->force_client_addr;
+  ctx->conf.client_addr = srv->client_addr;
+  ctx->conf.force_client_dev = srv->client_dev;
 
   ctx->radius = radius_client_init(&ctx, &ctx->conf);
   if (!ctx->radius) {
+    ctx->result.error = RC_ERR_SOCKET;
     return RC_ERROR;
   }
   int res = radius_client_register(ctx->radius, RADIUS_AUTH, receive_auth, ctx);
 
   if (res != 0) {
     radius_client_deinit(ctx->radius);
+    ctx->result.error = RC_ERR_SOCKET;
     return RC_ERROR;
   }
 
@@ -327,6 +717,10 @@ static int try_auth(rc_ctx ctx) {
 }
 
 int rc_authenticate(rc_ctx ctx, const char* username, const char* password) {
+  os_free(ctx->result.reply_message);
+  os_memset(&ctx->result, 0, sizeof(ctx->result));
+  ctx->result.server = -1;
+
   if (!ctx->server_count) {
     return RC_NO_SERV;
   }
@@ -334,18 +728,147 @@ int rc_authenticate(rc_ctx ctx, const char* username, const char* password) {
   ctx->username = username;
   ctx->password = password;
 
+  int res = RC_SERV_TIMEOUT;
+
   for (int i = 0; i < ctx->server_count; i++) {
     ctx->current_server = i;
-    int res = try_auth(ctx);
+    ctx->result.server = i;
+    res = try_auth(ctx);
     if (res != RC_SERV_TIMEOUT) {
-      return res;
+      break;
     }
   }
 
-  return RC_SERV_TIMEOUT;
+  /* Only responses with an invalid authenticator were received */
+  if (res == RC_SERV_TIMEOUT &&
+      ctx->result.error == RC_ERR_BAD_AUTHENTICATOR) {
+    res = RC_ERROR;
+  }
+
+  /* Passwords are owned by the caller, which wipes them */
+  ctx->username = NULL;
+  ctx->password = NULL;
+
+  return res;
 }
 
 struct vendor_attr* rc_get_attributes(rc_ctx ctx, int* count) {
-  *count = ctx->vendor_attrs_count;
-  return ctx->vendor_attrs;
+  *count = ctx->values_count;
+  return ctx->values;
+}
+
+const struct result_info* rc_get_result_info(rc_ctx ctx) {
+  return &ctx->result;
+}
+
+int rc_change_password(rc_ctx ctx,
+                       const char* username,
+                       const char* old_password,
+                       const char* new_password) {
+  ctx->new_password = new_password;
+  int res = rc_authenticate(ctx, username, old_password);
+  ctx->new_password = NULL;
+  return res;
+}
+
+static enum radius_das_res das_request(rc_das_ctx ctx,
+                                       int coa,
+                                       struct radius_das_attrs* attr) {
+  if (!ctx->cb) {
+    return RADIUS_DAS_SESSION_NOT_FOUND;
+  }
+
+  wpa_printf(MSG_DEBUG, "Received RADIUS %s request",
+             coa ? "CoA" : "Disconnect");
+
+  struct vendor_attr* values = NULL;
+  int values_count = 0;
+
+  /* The request is set by radius_das_coa(), see submodules.patch */
+  if (coa && attr->msg) {
+    for (int i = 0; i < ctx->vendor_attrs_count; i++) {
+      struct vendor_attr* req = ctx->vendor_attrs + i;
+      copy_attribute(&values, &values_count, attr->msg, req->vendor,
+                     req->subtype);
+    }
+  }
+
+  int res = ctx->cb(ctx->data, coa, attr->user_name, attr->user_name_len,
+                    attr->acct_session_id, attr->acct_session_id_len, values,
+                    values_count);
+
+  clear_values(values, &values_count);
+  os_free(values);
+
+  return res;
+}
+
+static enum radius_das_res das_disconnect(void* data,
+                                          struct radius_das_attrs* attr) {
+  return das_request(data, 0, attr);
+}
+
+static enum radius_das_res das_coa(void* data, struct radius_das_attrs* attr) {
+  return das_request(data, 1, attr);
+}
+
+rc_das_ctx rc_das_create(const char* shared_secret,
+                         u16 port,
+                         const u8* client_ip,
+                         unsigned int time_window,
+                         int require_event_timestamp,
+                         int require_message_authenticator) {
+  rc_das_ctx ctx = os_zalloc(sizeof(*ctx));
+  if (!ctx) {
+    return NULL;
+  }
+
+  struct radius_das_conf conf;
+  os_memset(&conf, 0, sizeof(conf));
+
+  conf.port = port;
+  conf.shared_secret = (const u8*)shared_secret;
+  conf.shared_secret_len = strlen(shared_secret);
+  conf.time_window = time_window;
+  conf.require_event_timestamp = require_event_timestamp;
+  conf.require_message_authenticator = require_message_authenticator;
+  conf.ctx = ctx;
+  conf.disconnect = das_disconnect;
+  conf.coa = das_coa;
+
+  if (client_ip) {
+    ctx->client_addr.af = AF_INET;
+    os_memcpy(&ctx->client_addr.u.v4, client_ip, sizeof(struct in_addr));
+    conf.client_addr = &ctx->client_addr;
+  }
+
+  ctx->das = radius_das_init(&conf);
+  if (!ctx->das) {
+    os_free(ctx);
+    return NULL;
+  }
+
+  return ctx;
+}
+
+int rc_das_add_attribute(rc_das_ctx ctx, u32 vendor, u8 subtype) {
+  return add_value(&ctx->vendor_attrs, &ctx->vendor_attrs_count, vendor,
+                   subtype, NULL, 0);
+}
+
+void rc_das_destroy(rc_das_ctx ctx) {
+  /* Also wipes the copy of the shared secret, see submodules.patch */
+  radius_das_deinit(ctx->das);
+  os_free(ctx->vendor_attrs);
+  os_free(ctx);
+}
+
+void rc_das_run(rc_das_ctx ctx, rc_das_cb cb, void* data) {
+  ctx->cb = cb;
+  ctx->data = data;
+
+  eloop_run();
+
+  ctx->cb = NULL;
+  ctx->data = NULL;
 }
@@ -8,8 +8,65 @@
 #include "eloop.h"
 #include "radius/radius.h"
 #include "radius/radius_client.h"
+#include "radius/radius_das.h"
 
 typedef struct context* rc_ctx;
+typedef struct das_context* rc_das_ctx;
+
+struct vendor_attr;
+
+/*
+ * Details about the last request
+ */
+struct result_info {
+  /* Index of the last server tried, -1 if none */
+  int server;
+  /* Code of the response, 0 if none was received */
+  int code;
+  /* One of RC_ERR_* */
+  int error;
+  /* Reply-Message of the response, NULL if none */
+  char* reply_message;
+  /* Whether a reject has MS-CHAP-Error E=648, the password expired */
+  int password_expired;
+};
+
+enum {
+  RC_ERR_NONE = 0,
+  RC_ERR_SOCKET,
+  RC_ERR_REQUEST,
+  RC_ERR_BAD_AUTHENTICATOR,
+  RC_ERR_UNEXPECTED_CODE
+};
+
+/*
+ * Dynamic authorization callback, coa is 0 for Disconnect-Request and 1 for
+ * CoA-Request. attrs holds the requested attributes found in a CoA-Request,
+ * they are freed when the callback returns. Returns a radius_das_res value.
+ */
+typedef int (*rc_das_cb)(void* data,
+                         int coa,
+                         const u8* user_name,
+                         size_t user_name_len,
+                         const u8* acct_session_id,
+                         size_t acct_session_id_len,
+                         const struct vendor_attr* attrs,
+                         int attrs_count);
+
+/*
+ * Packet trace callback, called with every RADIUS packet sent to or received
+ * from the server at the given index. sent is 1 for outgoing packets.
+ */
+typedef void (*rc_trace_cb)(void* data,
+                            int server,
+                            int sent,
+                            const u8* buf,
+                            size_t len);
+
+/*
+ * Log callback, level is one of the MSG_* wpa_printf levels
+ */
+typedef void (*rc_log_cb)(int level, const char* msg);
 
 /*
  * Free buffers coming from rc_* functions
@@ -27,18 +84,47 @@ int rc_init(void);
  */
 void rc_deinit(void);
 
+/*
+ * Route hostap debug output to the logger, messages below level are dropped
+ * (implemented in debug.c)
+ */
+void rc_set_logger(rc_log_cb cb);
+void rc_set_log_level(int level);
+
 rc_ctx rc_create_context(void);
 void rc_destroy_context(rc_ctx ctx);
 
 void rc_enable_debug(rc_ctx ctx);
+void rc_set_trace(rc_ctx ctx, rc_trace_cb cb, void* data);
 int rc_add_attribute(rc_ctx ctx, u32 vendor, u8 subtype);
 int rc_add_server(rc_ctx ctx,
                   const char* shared_secret,
                   const u8* ip,
                   int ipv6,
                   u16 port,
-                  u16 timeout);
+                  u16 timeout,
+                  const u8* source_ip,
+                  int source_ipv6,
+                  const char* interface);
 
 
 int rc_authenticate(rc_ctx ctx, const char* username, const char* password);
+int rc_change_password(rc_ctx ctx,
+                       const char* username,
+                       const char* old_password,
+                       const char* new_password);
 struct vendor_attr * rc_get_attributes(rc_ctx ctx, int *count);
+const struct result_info* rc_get_result_info(rc_ctx ctx);
+
+/*
+ * Dynamic Authorization Server (RFC 5176)
+ */
+rc_das_ctx rc_das_create(const char* shared_secret,
+                         u16 port,
+                         const u8* client_ip,
+                         unsigned int time_window,
+                         int require_event_timestamp,
+                         int require_message_authenticator);
+int rc_das_add_attribute(rc_das_ctx ctx, u32 vendor, u8 subtype);
+void rc_das_destroy(rc_das_ctx ctx);
+void rc_das_run(rc_das_ctx ctx, rc_das_cb cb, void* data);
@@ -5,6 +5,32 @@ pub(crate) struct Context {
     private: [u8; 0],
 }
 
+#[repr(C)]
+pub(crate) struct DasContext {
+    private: [u8; 0],
+}
+
+pub(crate) type DasCallback = extern "C" fn(
+    data: *mut c_void,
+    coa: c_int,
+    user_name: *const u8,
+    user_name_len: usize,
+    acct_session_id: *const u8,
+    acct_session_id_len: usize,
+    attrs: *const VendorAttribute,
+    attrs_count: c_int,
+) -> c_int;
+
+pub(crate) type TraceCallback = extern "C" fn(
+    data: *mut c_void,
+    server: c_int,
+    sent: c_int,
+    buf: *const u8,
+    len: usize,
+);
+
+pub(crate) type LogCallback = extern "C" fn(level: c_int, msg: *const c_char);
+
 #[allow(dead_code)]
 #[repr(C)]
 pub(crate) enum AuthResult {
@@ -16,6 +42,20 @@ pub(crate) enum AuthResult {
 }
 
 
+#[repr(C)]
+pub(crate) struct ResultInfo {
+    pub(crate) server: c_int,
+    pub(crate) code: c_int,
+    pub(crate) error: c_int,
+    pub(crate) reply_message: *const c_char,
+    pub(crate) password_expired: c_int,
+}
+
+pub(crate) const RC_ERR_SOCKET: c_int = 1;
+pub(crate) const RC_ERR_REQUEST: c_int = 2;
+pub(crate) const RC_ERR_BAD_AUTHENTICATOR: c_int = 3;
+pub(crate) const RC_ERR_UNEXPECTED_CODE: c_int = 4;
+
 #[repr(C)]
 pub(crate) struct VendorAttribute {
     pub(crate) vendor: u32,
@@ -27,6 +67,8 @@ pub(crate) struct VendorAttribute {
 extern "C" {
     pub(crate) fn rc_init() -> c_int;
     pub(crate) fn rc_deinit();
+    pub(crate) fn rc_set_logger(cb: LogCallback);
+    pub(crate) fn rc_set_log_level(level: c_int);
     pub(crate) fn rc_create_context() -> *mut Context;
     pub(crate) fn rc_destroy_context(ctx: *mut Context);
     pub(crate) fn rc_add_server(
@@ -36,8 +78,16 @@ extern "C" {
         ipv6: c_int,
         port: u16,
         timeout: u16,
+        source_ip: *const u8,
+        source_ipv6: c_int,
+        interface: *const c_char,
     ) -> c_int;
     pub(crate) fn rc_enable_debug(ctx: *mut Context);
+    pub(crate) fn rc_set_trace(
+        ctx: *mut Context,
+        cb: TraceCallback,
+        data: *mut c_void,
+    );
     pub(crate) fn rc_add_attribute(
         ctx: *mut Context,
         vendor: u32,
@@ -48,8 +98,34 @@ extern "C" {
         username: *const c_char,
         password: *const c_char,
     ) -> AuthResult;
+    pub(crate) fn rc_change_password(
+        ctx: *mut Context,
+        username: *const c_char,
+        old_password: *const c_char,
+        new_password: *const c_char,
+    ) -> AuthResult;
     pub(crate) fn rc_get_attributes(
         ctx: *mut Context,
         count: *mut c_int
     ) -> *mut VendorAttribute;
+    pub(crate) fn rc_get_result_info(ctx: *mut Context) -> *const ResultInfo;
+    pub(crate) fn rc_das_create(
+        shared_secret: *const c_char,
+        port: u16,
+        client_ip: *const u8,
+        time_window: c_uint,
+        require_event_timestamp: c_int,
+        require_message_authenticator: c_int,
+    ) -> *mut DasContext;
+    pub(crate) fn rc_das_add_attribute(
+        ctx: *mut DasContext,
+        vendor: u32,
+        subtype: u8,
+    ) -> c_int;
+    pub(crate) fn rc_das_destroy(ctx: *mut DasContext);
+    pub(crate) fn rc_das_run(
+        ctx: *mut DasContext,
+        cb: DasCallback,
+        data: *mut c_void,
+    );
 }
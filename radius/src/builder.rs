@@ -0,0 +1,102 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use crate::config::{Config, DynAuthConfig, Server};
+use crate::error::Error;
+
+/// Build a client configuration without a config file.
+///
+/// ```no_run
+/// use radius::{ConfigBuilder, Server};
+///
+/// let config = ConfigBuilder::new()
+///     .shared_secret("testing123")
+///     .server(Server::new("10.0.0.1").with_timeout(3))
+///     .attribute(1, 1)
+///     .build()?;
+/// # Ok::<(), radius::Error>(())
+/// ```
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        ConfigBuilder {
+            config: Config::default(),
+        }
+    }
+
+    pub fn shared_secret<S: Into<String>>(mut self, secret: S) -> Self {
+        self.config.shared_secret = Some(secret.into());
+        self
+    }
+
+    pub fn shared_secret_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.shared_secret_file = Some(path.into());
+        self
+    }
+
+    pub fn shared_secret_env<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.shared_secret_env = Some(name.into());
+        self
+    }
+
+    pub fn server(mut self, server: Server) -> Self {
+        self.config.servers.push(server);
+        self
+    }
+
+    /// Attribute returned with the authenticated user, vendor 0 requests a
+    /// standard attribute
+    pub fn attribute(mut self, vendor: u32, subtype: u8) -> Self {
+        self.config
+            .attributes
+            .get_or_insert_with(Vec::new)
+            .push((vendor, subtype));
+        self
+    }
+
+    /// Default timeout in seconds of servers without their own timeout
+    pub fn timeout(mut self, timeout: u16) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    pub fn source_address(mut self, address: IpAddr) -> Self {
+        self.config.source_address = Some(address.to_string());
+        self
+    }
+
+    pub fn bind_interface<S: Into<String>>(mut self, interface: S) -> Self {
+        self.config.bind_interface = Some(interface.into());
+        self
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.config.debug = Some(debug);
+        self
+    }
+
+    pub fn trace_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.trace_file = Some(path.into());
+        self
+    }
+
+    pub fn dynauth(mut self, dynauth: DynAuthConfig) -> Self {
+        self.config.dynauth = Some(dynauth);
+        self
+    }
+
+    /// Validate and return the configuration
+    pub fn build(self) -> Result<Config, Error> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        ConfigBuilder::new()
+    }
+}
@@ -1,15 +1,23 @@
-use std::ffi::CString;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::{c_int, c_void};
+use std::time::Instant;
 
 use crate::bindings::*;
 use crate::config::Config;
 use crate::credentials::Credentials;
-use crate::error::Error;
+use crate::error::{ClientErrorKind, Error, ServerResponse};
+use crate::logger;
+use crate::secret::{self, SecretCString};
+use crate::trace::Trace;
 use crate::user::Attribute;
 use crate::user::User;
 
 pub struct Client {
     ctx: *mut Context,
+    servers: Vec<SocketAddr>,
+    trace: Option<Box<RefCell<Trace>>>,
 }
 
 impl Client {
@@ -18,41 +26,66 @@ impl Client {
             return Err(Error::OSInitFailed);
         }
 
+        logger::init(config.debug.unwrap_or(false));
+
         let ctx = unsafe { rc_create_context() };
 
         if ctx.is_null() {
             return Err(Error::Memory);
         }
 
-        let client = Client { ctx };
+        let mut client = Client {
+            ctx,
+            servers: vec![],
+            trace: None,
+        };
 
         if config.servers.is_empty() {
             return Err(Error::NoServer);
         }
 
+        let mut trace = match &config.trace_file {
+            Some(path) => Some(
+                Trace::create(path, config.trace_mask_password.unwrap_or(true))
+                    .map_err(|err| Error::TraceFile(path.clone(), err))?,
+            ),
+            None => None,
+        };
+
+        let global_secret = config.resolve_shared_secret()?;
+
         for server in config.servers.iter() {
-            let addrs = server.address.to_socket_addrs();
-            let addrs = match addrs {
-                Err(_) => {
-                    format!("{}:1812", server.address).to_socket_addrs()?
-                }
-                Ok(addrs) => addrs,
-            };
+            let addrs = server.resolve_addrs()?;
 
             let shared_secret =
-                match (&config.shared_secret, &server.shared_secret) {
+                match (&global_secret, server.resolve_shared_secret()?) {
                     (None, None) => return Err(Error::NoSharedSecret),
-                    (_, Some(s)) => s.clone(),
+                    (_, Some(s)) => s,
                     (Some(s), _) => s.clone(),
                 };
 
-            if shared_secret.len() > 256 {
-                return Err(Error::SharedSecretTooLong);
-            }
+            let cs = secret::to_cstring(&shared_secret)?;
+
+            let source = config.source_address_for(server)?;
+
+            let (source_ip, source_ipv6): (Option<Vec<u8>>, _) = match source {
+                Some(IpAddr::V4(v4)) => (Some(v4.octets().into()), false),
+                Some(IpAddr::V6(v6)) => (Some(v6.octets().into()), true),
+                None => (None, false),
+            };
+
+            let interface = config.bind_interface_for(server)?;
+            let timeout = config.timeout_for(server);
 
-            let cs = CString::new(&shared_secret[..]).unwrap();
+            let mut added = false;
 
             for addr in addrs {
+                // The source address can only be used with servers of the
+                // same address family
+                if source.is_some() && addr.is_ipv6() != source_ipv6 {
+                    continue;
+                }
+
                 let (ip, ipv6, port): (Vec<u8>, _, _) = match addr {
                     SocketAddr::V4(v4) => {
                         (v4.ip().octets().into(), false, v4.port())
@@ -61,12 +94,6 @@ impl Client {
                         (v6.ip().octets().into(), true, v6.port())
                     }
                 };
-                let timeout = match server.timeout {
-                    None => config.timeout.unwrap_or(10),
-                    Some(t) => t,
-                };
-
-                let timeout = if timeout < 1 { 1 } else { timeout.min(30) };
 
                 unsafe {
                     if rc_add_server(
@@ -76,11 +103,29 @@ impl Client {
                         ipv6 as _,
                         port,
                         timeout,
+                        source_ip
+                            .as_ref()
+                            .map_or(std::ptr::null(), |ip| ip.as_ptr()),
+                        source_ipv6 as _,
+                        interface
+                            .as_ref()
+                            .map_or(std::ptr::null(), |i| i.as_ptr()),
                     ) != 0
                     {
                         return Err(Error::InvalidServer(addr.to_string()));
                     }
                 }
+
+                if let Some(trace) = trace.as_mut() {
+                    trace.add_peer(source, addr);
+                }
+
+                client.servers.push(addr);
+                added = true;
+            }
+
+            if !added {
+                return Err(Error::InvalidServer(server.address.clone()));
             }
         }
 
@@ -88,6 +133,18 @@ impl Client {
             unsafe { rc_enable_debug(client.ctx) };
         }
 
+        if let Some(trace) = trace {
+            let trace = Box::new(RefCell::new(trace));
+            unsafe {
+                rc_set_trace(
+                    client.ctx,
+                    trace_packet,
+                    &*trace as *const RefCell<Trace> as *mut c_void,
+                );
+            }
+            client.trace = Some(trace);
+        }
+
         if let Some(attrs) = &config.attributes {
             for (vendor, subtype) in attrs.iter() {
                 unsafe {
@@ -105,22 +162,89 @@ impl Client {
         &self,
         credentials: &Credentials,
     ) -> Result<User, Error> {
+        let u = CString::new(&credentials.username[..])
+            .map_err(|_| Error::InvalidCredentials)?;
+        let p = SecretCString::new(&credentials.password)
+            .ok_or(Error::InvalidCredentials)?;
+
+        debug!("Authenticating {}", credentials.username);
+
+        let start = Instant::now();
+        let res = unsafe { rc_authenticate(self.ctx, u.as_ptr(), p.as_ptr()) };
+        self.check_result(res, start)?;
+
+        let mut user = User::new(&credentials.username);
+        self.copy_attributes(&mut user);
+        Ok(user)
+    }
+
+    /// Change the user password using MS-CHAPv2 (MS-CHAP2-CPW), the
+    /// credentials must contain the current password.
+    pub fn change_password(
+        &self,
+        credentials: &Credentials,
+        new_password: &str,
+    ) -> Result<(), Error> {
+        let u = CString::new(&credentials.username[..])
+            .map_err(|_| Error::InvalidCredentials)?;
+        let p = SecretCString::new(&credentials.password)
+            .ok_or(Error::InvalidCredentials)?;
+        let n = SecretCString::new(new_password)
+            .ok_or(Error::InvalidCredentials)?;
+
+        debug!("Changing password of {}", credentials.username);
+
+        let start = Instant::now();
         let res = unsafe {
-            let u = CString::new(&credentials.username[..]).unwrap();
-            let p = CString::new(&credentials.password[..]).unwrap();
-            rc_authenticate(self.ctx, u.as_ptr(), p.as_ptr())
+            rc_change_password(self.ctx, u.as_ptr(), p.as_ptr(), n.as_ptr())
         };
-        return match res {
-            AuthResult::Accept => {
-                let mut user = User::new(&credentials.username);
-                self.copy_attributes(&mut user);
-                Ok(user)
+        self.check_result(res, start)
+    }
+
+    fn check_result(
+        &self,
+        res: AuthResult,
+        start: Instant,
+    ) -> Result<(), Error> {
+        let info = unsafe { &*rc_get_result_info(self.ctx) };
+
+        let response = ServerResponse {
+            server: self.servers.get(info.server as usize).cloned(),
+            code: if info.code > 0 {
+                Some(info.code as u8)
+            } else {
+                None
+            },
+            reply_message: if info.reply_message.is_null() {
+                None
+            } else {
+                let message = unsafe { CStr::from_ptr(info.reply_message) };
+                Some(message.to_string_lossy().into_owned())
+            },
+            password_expired: info.password_expired != 0,
+            elapsed: start.elapsed(),
+        };
+
+        debug!("RADIUS request finished: {}", response);
+
+        match res {
+            AuthResult::Accept => Ok(()),
+            AuthResult::Reject => Err(Error::AuthReject(response)),
+            AuthResult::Error => {
+                let kind = match info.error {
+                    RC_ERR_SOCKET => ClientErrorKind::Socket,
+                    RC_ERR_REQUEST => ClientErrorKind::Request,
+                    RC_ERR_BAD_AUTHENTICATOR => {
+                        ClientErrorKind::BadAuthenticator
+                    }
+                    RC_ERR_UNEXPECTED_CODE => ClientErrorKind::UnexpectedCode,
+                    _ => ClientErrorKind::Unknown,
+                };
+                Err(Error::RadiusClient { kind, response })
             }
-            AuthResult::Reject => Err(Error::AuthReject),
-            AuthResult::Error => Err(Error::RadiusClient),
             AuthResult::NoServ => Err(Error::NoServer),
-            AuthResult::ServTimeout => Err(Error::ServerTimeout),
-        };
+            AuthResult::ServTimeout => Err(Error::ServerTimeout(response)),
+        }
     }
 
     pub fn copy_attributes(&self, user: &mut User) {
@@ -128,9 +252,17 @@ impl Client {
         unsafe {
             let attrs = rc_get_attributes(self.ctx, &mut count);
 
+            // NULL when the reply has none of the requested attributes
+            if attrs.is_null() || count <= 0 {
+                return;
+            }
+
             let attrs = std::slice::from_raw_parts(attrs, count as _);
 
             for raw_attr in attrs {
+                if raw_attr.data.is_null() {
+                    continue;
+                }
                 let data =
                     std::slice::from_raw_parts(raw_attr.data, raw_attr.len);
                 let attr = Attribute {
@@ -144,6 +276,26 @@ impl Client {
     }
 }
 
+extern "C" fn trace_packet(
+    data: *mut c_void,
+    server: c_int,
+    sent: c_int,
+    buf: *const u8,
+    len: usize,
+) {
+    let trace = unsafe { &*(data as *const RefCell<Trace>) };
+    let packet = unsafe { std::slice::from_raw_parts(buf, len) };
+
+    // Tracing is best effort and must not fail the authentication
+    if let Err(err) =
+        trace
+            .borrow_mut()
+            .packet(server as usize, sent != 0, packet)
+    {
+        warn!("Cannot write packet trace: {}", err);
+    }
+}
+
 impl Drop for Client {
     fn drop(&mut self) {
         unsafe {
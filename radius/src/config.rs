@@ -1,35 +1,355 @@
-use serde::{Deserialize};
-use std::path::PathBuf;
 use crate::error::Error;
+use crate::secret;
+use serde::Deserialize;
+use std::ffi::CString;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use zeroize::{Zeroize, Zeroizing};
 
+const DEFAULT_PORT: u16 = 1812;
+const DEFAULT_TIMEOUT: u16 = 10;
+const MAX_TIMEOUT: u16 = 30;
 
+use common::config::UnknownField;
 use common::serde::decode_attrs;
 
 #[derive(Deserialize, Debug)]
 pub struct Server {
     pub address: String,
     pub shared_secret: Option<String>,
+    pub shared_secret_file: Option<PathBuf>,
+    pub shared_secret_env: Option<String>,
+    pub shared_secret_credential: Option<String>,
     pub timeout: Option<u16>,
+    pub source_address: Option<String>,
+    pub bind_interface: Option<String>,
+}
+
+impl Server {
+    pub fn new<S: Into<String>>(address: S) -> Self {
+        Server {
+            address: address.into(),
+            shared_secret: None,
+            shared_secret_file: None,
+            shared_secret_env: None,
+            shared_secret_credential: None,
+            timeout: None,
+            source_address: None,
+            bind_interface: None,
+        }
+    }
+
+    pub fn with_shared_secret<S: Into<String>>(mut self, secret: S) -> Self {
+        self.shared_secret = Some(secret.into());
+        self
+    }
+
+    pub fn with_shared_secret_file<P: Into<PathBuf>>(
+        mut self,
+        path: P,
+    ) -> Self {
+        self.shared_secret_file = Some(path.into());
+        self
+    }
+
+    pub fn with_shared_secret_env<S: Into<String>>(mut self, name: S) -> Self {
+        self.shared_secret_env = Some(name.into());
+        self
+    }
+
+    /// Timeout in seconds, between 1 and 30
+    pub fn with_timeout(mut self, timeout: u16) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_source_address(mut self, address: IpAddr) -> Self {
+        self.source_address = Some(address.to_string());
+        self
+    }
+
+    pub fn with_bind_interface<S: Into<String>>(
+        mut self,
+        interface: S,
+    ) -> Self {
+        self.bind_interface = Some(interface.into());
+        self
+    }
+
+    /// Resolve the server address, the default port is used when the address
+    /// has none.
+    pub fn resolve_addrs(&self) -> Result<Vec<SocketAddr>, Error> {
+        let addrs = match self.address.to_socket_addrs() {
+            Ok(addrs) => addrs,
+            Err(_) => format!("{}:{}", self.address, DEFAULT_PORT)
+                .to_socket_addrs()
+                .map_err(|_| Error::InvalidServer(self.address.clone()))?,
+        };
+        Ok(addrs.collect())
+    }
+
+    pub fn resolve_shared_secret(
+        &self,
+    ) -> Result<Option<Zeroizing<String>>, Error> {
+        secret::resolve(
+            &self.shared_secret,
+            &self.shared_secret_file,
+            &self.shared_secret_env,
+            &self.shared_secret_credential,
+        )
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shared_secret.zeroize();
+    }
 }
 
 #[derive(Deserialize, Debug)]
+pub struct DynAuthConfig {
+    pub client: String,
+    pub port: Option<u16>,
+    pub shared_secret: Option<String>,
+    pub shared_secret_file: Option<PathBuf>,
+    pub shared_secret_env: Option<String>,
+    pub shared_secret_credential: Option<String>,
+    pub time_window: Option<u32>,
+    pub require_event_timestamp: Option<bool>,
+    pub require_message_authenticator: Option<bool>,
+}
+
+impl DynAuthConfig {
+    pub fn client_addr(&self) -> Result<Ipv4Addr, Error> {
+        self.client
+            .parse()
+            .map_err(|_| Error::InvalidDynAuthClient(self.client.clone()))
+    }
+
+    pub fn resolve_shared_secret(
+        &self,
+    ) -> Result<Option<Zeroizing<String>>, Error> {
+        secret::resolve(
+            &self.shared_secret,
+            &self.shared_secret_file,
+            &self.shared_secret_env,
+            &self.shared_secret_credential,
+        )
+    }
+}
+
+impl Drop for DynAuthConfig {
+    fn drop(&mut self) {
+        self.shared_secret.zeroize();
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
 pub struct Config {
     pub shared_secret: Option<String>,
+    pub shared_secret_file: Option<PathBuf>,
+    pub shared_secret_env: Option<String>,
+    pub shared_secret_credential: Option<String>,
     pub servers: Vec<Server>,
     pub debug: Option<bool>,
     pub timeout: Option<u16>,
+    pub source_address: Option<String>,
+    pub bind_interface: Option<String>,
     #[serde(deserialize_with = "decode_attrs")]
     pub attributes: Option<Vec<(u32, u8)>>,
+    pub dynauth: Option<DynAuthConfig>,
+    pub trace_file: Option<PathBuf>,
+    pub trace_mask_password: Option<bool>,
 }
 
 impl Config {
+    /// Check the whole configuration, including secret sources and server
+    /// name resolution. Every problem found is reported at once in
+    /// `Error::InvalidConfig`.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut errors = vec![];
 
+        if self.servers.is_empty() {
+            errors.push(Error::NoServer);
+        }
+
+        let (global_secret, global_failed) = match self.resolve_shared_secret()
+        {
+            Ok(secret) => (secret, false),
+            Err(err) => {
+                errors.push(err);
+                (None, true)
+            }
+        };
+
+        for server in self.servers.iter() {
+            let mut server_errors = vec![];
+
+            server_errors.extend(check_secret(
+                server.resolve_shared_secret(),
+                &global_secret,
+                global_failed,
+            ));
+
+            let source =
+                self.source_address_for(server).unwrap_or_else(|err| {
+                    server_errors.push(err);
+                    None
+                });
+
+            if let Err(err) = self.bind_interface_for(server) {
+                server_errors.push(err);
+            }
+
+            errors.extend(server_errors.into_iter().map(|err| {
+                Error::ServerConfig(server.address.clone(), Box::new(err))
+            }));
+
+            match server.resolve_addrs() {
+                Ok(addrs) => {
+                    // The source address can only be used with servers of
+                    // the same address family
+                    let usable = match source {
+                        Some(source) => addrs
+                            .iter()
+                            .any(|addr| addr.is_ipv6() == source.is_ipv6()),
+                        None => !addrs.is_empty(),
+                    };
+                    if !usable {
+                        errors
+                            .push(Error::InvalidServer(server.address.clone()))
+                    }
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if let Some(dynauth) = &self.dynauth {
+            if let Err(err) = dynauth.client_addr() {
+                errors.push(err);
+            }
+
+            errors.extend(check_secret(
+                dynauth.resolve_shared_secret(),
+                &global_secret,
+                global_failed,
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(errors))
+        }
+    }
+
+    /// Source address of a server, the server setting takes precedence
+    pub(crate) fn source_address_for(
+        &self,
+        server: &Server,
+    ) -> Result<Option<IpAddr>, Error> {
+        match (&server.source_address, &self.source_address) {
+            (Some(s), _) | (None, Some(s)) => s
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::InvalidSourceAddress(s.clone())),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Interface a server socket is bound to, the server setting takes
+    /// precedence
+    pub(crate) fn bind_interface_for(
+        &self,
+        server: &Server,
+    ) -> Result<Option<CString>, Error> {
+        match (&server.bind_interface, &self.bind_interface) {
+            (Some(i), _) | (None, Some(i)) => CString::new(&i[..])
+                .map(Some)
+                .map_err(|_| Error::InvalidInterface(i.clone())),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Timeout of a server in seconds, clamped between 1 and 30
+    pub(crate) fn timeout_for(&self, server: &Server) -> u16 {
+        let timeout =
+            server.timeout.or(self.timeout).unwrap_or(DEFAULT_TIMEOUT);
+        timeout.clamp(1, MAX_TIMEOUT)
+    }
+
+    pub fn resolve_shared_secret(
+        &self,
+    ) -> Result<Option<Zeroizing<String>>, Error> {
+        secret::resolve(
+            &self.shared_secret,
+            &self.shared_secret_file,
+            &self.shared_secret_env,
+            &self.shared_secret_credential,
+        )
+    }
+
+    /// Read the radius section of a config file, unknown fields are logged
+    /// as warnings.
     pub fn read_file<S: Into<PathBuf>>(path: S) -> Result<Config, Error> {
-        let config = std::fs::read_to_string(path.into())?;
-        let config = toml::from_str::<toml::Value>(&config)?;
-        let config = config.get("radius").ok_or(Error::ConfigFormat)?;
-        let config = config.clone().try_into::<Config>()?;
-        Ok(config)
+        Config::load(path.into(), false)
+    }
+
+    /// Read the radius section of a config file, unknown fields are rejected
+    pub fn read_file_strict<S: Into<PathBuf>>(
+        path: S,
+    ) -> Result<Config, Error> {
+        Config::load(path.into(), true)
     }
+
+    fn load(path: PathBuf, strict: bool) -> Result<Config, Error> {
+        let config = Zeroizing::new(std::fs::read_to_string(path)?);
+        let (config, unknown) =
+            common::config::from_str::<ConfigFile>(&config)?;
+
+        // Other sections belong to other components
+        let unknown: Vec<UnknownField> = unknown
+            .into_iter()
+            .filter(|field| field.key.starts_with("radius."))
+            .collect();
+
+        if strict && !unknown.is_empty() {
+            return Err(Error::UnknownFields(unknown));
+        }
+
+        for field in unknown.iter() {
+            warn!("Ignoring {}", field);
+        }
+
+        config.radius.ok_or(Error::ConfigFormat)
+    }
+}
+
+impl Drop for Config {
+    fn drop(&mut self) {
+        self.shared_secret.zeroize();
+    }
+}
+
+/// Check a secret which falls back on the global secret, a missing secret is
+/// not reported when the global secret already failed.
+fn check_secret(
+    secret: Result<Option<Zeroizing<String>>, Error>,
+    global: &Option<Zeroizing<String>>,
+    global_failed: bool,
+) -> Option<Error> {
+    let secret = match (secret, global) {
+        (Err(err), _) => return Some(err),
+        (Ok(Some(secret)), _) => secret,
+        (Ok(None), Some(global)) => global.clone(),
+        (Ok(None), None) if global_failed => return None,
+        (Ok(None), None) => return Some(Error::NoSharedSecret),
+    };
+    secret::check(&secret).err()
 }
 
+/// Only the radius section is deserialized, other sections are ignored
+#[derive(Deserialize)]
+struct ConfigFile {
+    radius: Option<Config>,
+}
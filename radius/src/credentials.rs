@@ -1,3 +1,9 @@
+use zeroize::Zeroize;
+
+use crate::secret;
+
+/// Username and password, the password is locked in memory and wiped when
+/// the credentials are dropped.
 pub struct Credentials {
     pub(crate) username: String,
     pub(crate) password: String,
@@ -8,9 +14,17 @@ impl Credentials {
         username: S,
         password: S,
     ) -> Self {
+        let password = password.into();
+        secret::lock(password.as_ptr(), password.capacity());
         Credentials {
             username: username.into(),
-            password: password.into(),
+            password,
         }
     }
 }
+
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * Replacement for hostap src/utils/wpa_debug.c
+ *
+ * Debug messages are formatted and passed to the logger registered with
+ * rc_set_logger instead of being printed on stdout. Messages are dropped
+ * when no logger is registered.
+ */
+
+#include "includes.h"
+
+#include "common.h"
+
+#include "bindings.h"
+
+#define RC_LOG_MAX_LEN 2048
+
+int wpa_debug_level = MSG_ERROR;
+int wpa_debug_show_keys = 0;
+int wpa_debug_timestamp = 0;
+
+static rc_log_cb log_cb = NULL;
+
+void rc_set_logger(rc_log_cb cb) {
+  log_cb = cb;
+}
+
+void rc_set_log_level(int level) {
+  wpa_debug_level = level;
+}
+
+static void log_message(int level, const char* msg) {
+  if (log_cb) {
+    log_cb(level, msg);
+  }
+}
+
+void wpa_debug_print_timestamp(void) {}
+
+void wpa_printf(int level, const char* fmt, ...) {
+  char buf[RC_LOG_MAX_LEN];
+  va_list ap;
+
+  if (level < wpa_debug_level || !log_cb) {
+    return;
+  }
+
+  va_start(ap, fmt);
+  vsnprintf(buf, sizeof(buf), fmt, ap);
+  va_end(ap);
+
+  log_message(level, buf);
+}
+
+static void hexdump(int level,
+                    const char* title,
+                    const void* buf,
+                    size_t len,
+                    int show,
+                    int ascii) {
+  char msg[RC_LOG_MAX_LEN];
+  const u8* data = buf;
+  size_t pos;
+  int res;
+
+  if (level < wpa_debug_level || !log_cb) {
+    return;
+  }
+
+  res = os_snprintf(msg, sizeof(msg), "%s - hexdump%s(len=%lu):", title,
+                    ascii ? "_ascii" : "", (unsigned long)len);
+  if (os_snprintf_error(sizeof(msg), res)) {
+    return;
+  }
+  pos = res;
+
+  if (!data) {
+    os_snprintf(msg + pos, sizeof(msg) - pos, " [NULL]");
+  } else if (!show) {
+    os_snprintf(msg + pos, sizeof(msg) - pos, " [REMOVED]");
+  } else {
+    for (size_t i = 0; i < len && pos + 4 < sizeof(msg); i++) {
+      if (ascii && data[i] >= 32 && data[i] < 127) {
+        res = os_snprintf(msg + pos, sizeof(msg) - pos, " %c", data[i]);
+      } else {
+        res = os_snprintf(msg + pos, sizeof(msg) - pos, " %02x", data[i]);
+      }
+      if (os_snprintf_error(sizeof(msg) - pos, res)) {
+        break;
+      }
+      pos += res;
+    }
+  }
+
+  log_message(level, msg);
+}
+
+void wpa_hexdump(int level, const char* title, const void* buf, size_t len) {
+  hexdump(level, title, buf, len, 1, 0);
+}
+
+void wpa_hexdump_key(int level,
+                     const char* title,
+                     const void* buf,
+                     size_t len) {
+  hexdump(level, title, buf, len, wpa_debug_show_keys, 0);
+}
+
+void wpa_hexdump_ascii(int level,
+                       const char* title,
+                       const void* buf,
+                       size_t len) {
+  hexdump(level, title, buf, len, 1, 1);
+}
+
+void wpa_hexdump_ascii_key(int level,
+                           const char* title,
+                           const void* buf,
+                           size_t len) {
+  hexdump(level, title, buf, len, wpa_debug_show_keys, 1);
+}
+
+/*
+ * hostapd_logger levels go from HOSTAPD_LEVEL_DEBUG_VERBOSE to
+ * HOSTAPD_LEVEL_WARNING, they are mapped to wpa_printf levels.
+ */
+static int logger_level(int level) {
+  switch (level) {
+    case HOSTAPD_LEVEL_DEBUG_VERBOSE:
+      return MSG_MSGDUMP;
+    case HOSTAPD_LEVEL_DEBUG:
+      return MSG_DEBUG;
+    case HOSTAPD_LEVEL_INFO:
+    case HOSTAPD_LEVEL_NOTICE:
+      return MSG_INFO;
+    default:
+      return MSG_WARNING;
+  }
+}
+
+void hostapd_logger(void* ctx,
+                    const u8* addr,
+                    unsigned int module,
+                    int level,
+                    const char* fmt,
+                    ...) {
+  char buf[RC_LOG_MAX_LEN];
+  va_list ap;
+
+  level = logger_level(level);
+
+  if (level < wpa_debug_level || !log_cb) {
+    return;
+  }
+
+  va_start(ap, fmt);
+  vsnprintf(buf, sizeof(buf), fmt, ap);
+  va_end(ap);
+
+  log_message(level, buf);
+}
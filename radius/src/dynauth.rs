@@ -0,0 +1,185 @@
+use std::os::raw::{c_int, c_void};
+
+use crate::bindings::*;
+use crate::config::Config;
+use crate::error::Error;
+use crate::logger;
+use crate::secret;
+use crate::user::Attribute;
+
+const DEFAULT_PORT: u16 = 3799;
+const DEFAULT_TIME_WINDOW: u32 = 300;
+
+/// Dynamic authorization request (RFC 5176) received from the RADIUS server
+#[derive(Clone, Debug, PartialEq)]
+pub enum DynAuthRequest {
+    Disconnect {
+        username: Option<String>,
+        session_id: Option<String>,
+    },
+    /// The attributes are the ones of `radius.attributes` found in the
+    /// request
+    CoA {
+        username: Option<String>,
+        session_id: Option<String>,
+        attributes: Vec<Attribute>,
+    },
+}
+
+/// Outcome of a dynamic authorization request, sent back as ACK or NAK
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DynAuthResult {
+    Success = 0,
+    NasMismatch,
+    SessionNotFound,
+    MultiSessionMatch,
+    Failed,
+}
+
+pub struct DynAuthServer {
+    ctx: *mut DasContext,
+}
+
+impl DynAuthServer {
+    pub fn with_config(config: &Config) -> Result<Self, Error> {
+        let dynauth = config.dynauth.as_ref().ok_or(Error::NoDynAuth)?;
+
+        let client = dynauth.client_addr()?;
+
+        let shared_secret = match (
+            config.resolve_shared_secret()?,
+            dynauth.resolve_shared_secret()?,
+        ) {
+            (None, None) => return Err(Error::NoSharedSecret),
+            (_, Some(s)) => s,
+            (Some(s), _) => s,
+        };
+
+        let cs = secret::to_cstring(&shared_secret)?;
+
+        if unsafe { rc_init() } != 0 {
+            return Err(Error::OSInitFailed);
+        }
+
+        logger::init(config.debug.unwrap_or(false));
+
+        let ctx = unsafe {
+            rc_das_create(
+                cs.as_ptr(),
+                dynauth.port.unwrap_or(DEFAULT_PORT),
+                client.octets().as_ptr(),
+                dynauth.time_window.unwrap_or(DEFAULT_TIME_WINDOW),
+                dynauth.require_event_timestamp.unwrap_or(false) as _,
+                dynauth.require_message_authenticator.unwrap_or(false) as _,
+            )
+        };
+
+        if ctx.is_null() {
+            unsafe { rc_deinit() };
+            return Err(Error::DynAuthInit);
+        }
+
+        let server = DynAuthServer { ctx };
+
+        if let Some(attrs) = &config.attributes {
+            for (vendor, subtype) in attrs.iter() {
+                unsafe {
+                    if rc_das_add_attribute(server.ctx, *vendor, *subtype) != 0
+                    {
+                        return Err(Error::Memory);
+                    }
+                }
+            }
+        }
+
+        Ok(server)
+    }
+
+    /// Process requests until the event loop is terminated, the handler is
+    /// called once per request and its result is sent back to the client.
+    pub fn run<F>(&mut self, handler: F)
+    where
+        F: FnMut(DynAuthRequest) -> DynAuthResult,
+    {
+        let mut handler = handler;
+        unsafe {
+            rc_das_run(
+                self.ctx,
+                handle_request::<F>,
+                &mut handler as *mut F as *mut c_void,
+            );
+        }
+    }
+}
+
+extern "C" fn handle_request<F>(
+    data: *mut c_void,
+    coa: c_int,
+    user_name: *const u8,
+    user_name_len: usize,
+    acct_session_id: *const u8,
+    acct_session_id_len: usize,
+    attrs: *const VendorAttribute,
+    attrs_count: c_int,
+) -> c_int
+where
+    F: FnMut(DynAuthRequest) -> DynAuthResult,
+{
+    let handler = unsafe { &mut *(data as *mut F) };
+
+    let username = to_string(user_name, user_name_len);
+    let session_id = to_string(acct_session_id, acct_session_id_len);
+
+    let req = if coa != 0 {
+        DynAuthRequest::CoA {
+            username,
+            session_id,
+            attributes: to_attributes(attrs, attrs_count),
+        }
+    } else {
+        DynAuthRequest::Disconnect {
+            username,
+            session_id,
+        }
+    };
+
+    handler(req) as c_int
+}
+
+fn to_string(data: *const u8, len: usize) -> Option<String> {
+    if data.is_null() {
+        return None;
+    }
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    Some(String::from_utf8_lossy(data).into_owned())
+}
+
+fn to_attributes(
+    attrs: *const VendorAttribute,
+    count: c_int,
+) -> Vec<Attribute> {
+    if attrs.is_null() || count <= 0 {
+        return vec![];
+    }
+    let attrs = unsafe { std::slice::from_raw_parts(attrs, count as _) };
+
+    attrs
+        .iter()
+        .filter(|attr| !attr.data.is_null())
+        .map(|attr| Attribute {
+            vendor: attr.vendor,
+            subtype: attr.subtype,
+            data: unsafe { std::slice::from_raw_parts(attr.data, attr.len) }
+                .to_vec(),
+        })
+        .collect()
+}
+
+impl Drop for DynAuthServer {
+    fn drop(&mut self) {
+        unsafe {
+            rc_das_destroy(self.ctx);
+            rc_deinit();
+        }
+    }
+}
@@ -1,5 +1,74 @@
+use common::config::UnknownField;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
 use thiserror::Error;
 
+/// Details about the exchange with the RADIUS server which led to an error
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerResponse {
+    /// Last server tried
+    pub server: Option<SocketAddr>,
+    /// Code of the response packet, if one was received
+    pub code: Option<u8>,
+    /// Reply-Message attribute of the response
+    pub reply_message: Option<String>,
+    /// The reject says the password expired and must be changed
+    /// (MS-CHAP-Error E=648)
+    pub password_expired: bool,
+    /// Time spent on the request, across all servers
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for ServerResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.server {
+            Some(server) => write!(f, "server {}", server)?,
+            None => write!(f, "no server")?,
+        }
+        if let Some(code) = self.code {
+            write!(f, ", code {}", code)?;
+        }
+        if let Some(message) = &self.reply_message {
+            write!(f, ", reply message {:?}", message)?;
+        }
+        if self.password_expired {
+            write!(f, ", password expired")?;
+        }
+        write!(f, ", after {}ms", self.elapsed.as_millis())
+    }
+}
+
+/// Cause of a RADIUS client failure
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientErrorKind {
+    /// The client socket could not be created or bound
+    Socket,
+    /// The request could not be built or sent
+    Request,
+    /// The response authenticator is invalid, usually a shared secret
+    /// mismatch
+    BadAuthenticator,
+    /// The response code is not expected for the request
+    UnexpectedCode,
+    Unknown,
+}
+
+impl fmt::Display for ClientErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ClientErrorKind::Socket => "cannot open socket",
+            ClientErrorKind::Request => "cannot send request",
+            ClientErrorKind::BadAuthenticator => {
+                "invalid response authenticator"
+            }
+            ClientErrorKind::UnexpectedCode => "unexpected response code",
+            ClientErrorKind::Unknown => "unknown error",
+        };
+        f.write_str(s)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Could not init OS")]
@@ -8,22 +77,63 @@ pub enum Error {
     Memory,
     #[error("No server provided")]
     NoServer,
-    #[error("All servers timed out")]
-    ServerTimeout,
+    #[error("All servers timed out ({0})")]
+    ServerTimeout(ServerResponse),
     #[error("Invalid server `{0}`")]
     InvalidServer(String),
+    #[error("Invalid source address `{0}`")]
+    InvalidSourceAddress(String),
+    #[error("Invalid bind interface `{0}`")]
+    InvalidInterface(String),
+    #[error("No dynamic authorization configuration provided")]
+    NoDynAuth,
+    #[error("Cannot start dynamic authorization server")]
+    DynAuthInit,
+    #[error("Invalid dynamic authorization client `{0}`")]
+    InvalidDynAuthClient(String),
     #[error("No shared secret provided")]
     NoSharedSecret,
     #[error("Shared secret too long (max 256 chars)")]
     SharedSecretTooLong,
+    #[error("Shared secret contains a NUL character")]
+    InvalidSharedSecret,
+    #[error("Conflicting shared secret sources, only one can be set")]
+    SharedSecretConflict,
+    #[error("Cannot read shared secret file `{0}`: {1}")]
+    SharedSecretFile(std::path::PathBuf, std::io::Error),
+    #[error("Shared secret environment variable `{0}` is not set")]
+    SharedSecretEnv(String),
+    #[error("Systemd credential `{0}` not found")]
+    SharedSecretCredential(String),
+    #[error("Cannot create trace file `{0}`: {1}")]
+    TraceFile(std::path::PathBuf, std::io::Error),
     #[error("Underlying IO error: {0}")]
     IOError(#[from] std::io::Error),
-    #[error("RADIUS client failure")]
-    RadiusClient,
-    #[error("Authentication rejected, wrong credentials")]
-    AuthReject,
+    #[error("RADIUS client failure: {kind} ({response})")]
+    RadiusClient {
+        kind: ClientErrorKind,
+        response: ServerResponse,
+    },
+    #[error("Credentials contain a NUL character")]
+    InvalidCredentials,
+    #[error("Authentication rejected, wrong credentials ({0})")]
+    AuthReject(ServerResponse),
     #[error("TOML syntax error: {0}")]
     TomlError(#[from] toml::de::Error),
     #[error("Config format error")]
     ConfigFormat,
+    #[error("Server `{0}`: {1}")]
+    ServerConfig(String, Box<Error>),
+    #[error("Invalid configuration: {}", join(.0))]
+    InvalidConfig(Vec<Error>),
+    #[error("Invalid configuration: {}", join(.0))]
+    UnknownFields(Vec<UnknownField>),
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
 }
@@ -1,14 +1,26 @@
+#[macro_use]
+extern crate log;
+
 mod bindings;
 
+mod builder;
 mod client;
 mod user;
 mod credentials;
 mod config;
+mod dynauth;
 mod error;
+mod logger;
+mod secret;
+mod trace;
 
 pub use client::Client;
 pub use user::User;
 pub use user::Attribute;
+pub use builder::ConfigBuilder;
 pub use config::Config;
+pub use config::DynAuthConfig;
+pub use config::Server;
+pub use dynauth::{DynAuthRequest, DynAuthResult, DynAuthServer};
 pub use credentials::Credentials;
-pub use error::Error;
+pub use error::{ClientErrorKind, Error, ServerResponse};
@@ -0,0 +1,51 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+
+use log::{Level, LevelFilter};
+
+use crate::bindings::*;
+
+const TARGET: &str = "radius::hostap";
+
+// wpa_printf levels
+const MSG_EXCESSIVE: c_int = 0;
+const MSG_MSGDUMP: c_int = 1;
+const MSG_DEBUG: c_int = 2;
+const MSG_INFO: c_int = 3;
+const MSG_WARNING: c_int = 4;
+const MSG_ERROR: c_int = 5;
+
+/// Route hostap messages to the `log` facade.
+///
+/// Debug messages are only formatted when debug is enabled in the config and
+/// allowed by the `log` max level, otherwise warnings and errors are kept.
+pub(crate) fn init(debug: bool) {
+    let level = match log::max_level() {
+        LevelFilter::Off => MSG_ERROR + 1,
+        LevelFilter::Error => MSG_ERROR,
+        LevelFilter::Warn => MSG_WARNING,
+        LevelFilter::Info => MSG_INFO,
+        LevelFilter::Debug => MSG_DEBUG,
+        LevelFilter::Trace => MSG_EXCESSIVE,
+    };
+
+    let level = if debug { level } else { level.max(MSG_WARNING) };
+
+    unsafe {
+        rc_set_logger(log_message);
+        rc_set_log_level(level);
+    }
+}
+
+extern "C" fn log_message(level: c_int, msg: *const c_char) {
+    let level = match level {
+        MSG_EXCESSIVE | MSG_MSGDUMP => Level::Trace,
+        MSG_DEBUG => Level::Debug,
+        MSG_INFO => Level::Info,
+        MSG_WARNING => Level::Warn,
+        _ => Level::Error,
+    };
+
+    let msg = unsafe { CStr::from_ptr(msg) };
+    log!(target: TARGET, level, "{}", msg.to_string_lossy());
+}
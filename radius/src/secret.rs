@@ -0,0 +1,135 @@
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::Error;
+
+const MAX_SECRET_LEN: usize = 256;
+const CREDENTIALS_DIRECTORY: &str = "CREDENTIALS_DIRECTORY";
+
+/// Resolve a shared secret from one of its possible sources, only one of
+/// them may be set.
+///
+/// The secret value must never be part of a returned error.
+pub(crate) fn resolve(
+    inline: &Option<String>,
+    file: &Option<PathBuf>,
+    env: &Option<String>,
+    credential: &Option<String>,
+) -> Result<Option<Zeroizing<String>>, Error> {
+    let count = [
+        inline.is_some(),
+        file.is_some(),
+        env.is_some(),
+        credential.is_some(),
+    ]
+    .iter()
+    .filter(|set| **set)
+    .count();
+
+    if count > 1 {
+        return Err(Error::SharedSecretConflict);
+    }
+
+    if let Some(secret) = inline {
+        return Ok(Some(Zeroizing::new(secret.clone())));
+    }
+
+    if let Some(path) = file {
+        return read_secret_file(path)
+            .map(Some)
+            .map_err(|err| Error::SharedSecretFile(path.clone(), err));
+    }
+
+    if let Some(name) = env {
+        return std::env::var(name)
+            .map(|secret| Some(Zeroizing::new(secret)))
+            .map_err(|_| Error::SharedSecretEnv(name.clone()));
+    }
+
+    if let Some(name) = credential {
+        let dir = std::env::var_os(CREDENTIALS_DIRECTORY)
+            .ok_or_else(|| Error::SharedSecretCredential(name.clone()))?;
+        let path = Path::new(&dir).join(name);
+        return read_secret_file(&path)
+            .map(Some)
+            .map_err(|_| Error::SharedSecretCredential(name.clone()));
+    }
+
+    Ok(None)
+}
+
+/// Check that a shared secret can be used by the C client
+pub(crate) fn check(secret: &str) -> Result<(), Error> {
+    if secret.is_empty() {
+        return Err(Error::NoSharedSecret);
+    }
+
+    if secret.len() > MAX_SECRET_LEN {
+        return Err(Error::SharedSecretTooLong);
+    }
+
+    if secret.contains('\0') {
+        return Err(Error::InvalidSharedSecret);
+    }
+
+    Ok(())
+}
+
+/// Convert a shared secret for the C client
+pub(crate) fn to_cstring(secret: &str) -> Result<SecretCString, Error> {
+    check(secret)?;
+    SecretCString::new(secret).ok_or(Error::InvalidSharedSecret)
+}
+
+fn read_secret_file(path: &Path) -> Result<Zeroizing<String>, std::io::Error> {
+    let secret = Zeroizing::new(std::fs::read_to_string(path)?);
+    let secret = secret.trim_end_matches(['\n', '\r']);
+    Ok(Zeroizing::new(secret.to_string()))
+}
+
+/// NUL terminated copy of a secret passed to the C client, locked in memory
+/// and wiped on drop.
+pub(crate) struct SecretCString {
+    data: Vec<u8>,
+}
+
+impl SecretCString {
+    pub(crate) fn new(secret: &str) -> Option<Self> {
+        let data = match CString::new(secret) {
+            Ok(data) => data.into_bytes_with_nul(),
+            Err(err) => {
+                // The error owns a copy of the secret
+                err.into_vec().zeroize();
+                return None;
+            }
+        };
+        lock(data.as_ptr(), data.capacity());
+        Some(SecretCString { data })
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const c_char {
+        self.data.as_ptr() as *const c_char
+    }
+}
+
+impl Drop for SecretCString {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+/// Prevent the memory from being swapped, failures are ignored as the
+/// locked memory limit can be low for unprivileged processes. The pages stay
+/// locked for the process lifetime: unlocking them when a secret is dropped
+/// would also unlock another secret sharing a page.
+#[cfg(unix)]
+pub(crate) fn lock(ptr: *const u8, len: usize) {
+    if len > 0 {
+        unsafe { libc::mlock(ptr as *const libc::c_void, len) };
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock(_ptr: *const u8, _len: usize) {}
@@ -0,0 +1,231 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_SNAPLEN: u32 = 65535;
+/// Raw IP packets, the version is read from the first nibble
+const LINKTYPE_RAW: u32 = 101;
+
+const IPPROTO_UDP: u8 = 17;
+const UDP_HEADER_LEN: usize = 8;
+
+const RADIUS_HEADER_LEN: usize = 20;
+const ATTR_USER_PASSWORD: u8 = 2;
+const ATTR_VENDOR_SPECIFIC: u8 = 26;
+
+/// Microsoft vendor attributes of a password change (RFC 2548)
+const VENDOR_MICROSOFT: u32 = 311;
+const MS_CHAP_NT_ENC_PW: u8 = 6;
+const MS_CHAP2_CPW: u8 = 27;
+
+/// Write RADIUS packets to a pcap file that can be opened in Wireshark.
+///
+/// Packets are appended to the file, it is created readable by its owner
+/// only and a symlink is refused. The local port is not known, packets use
+/// port 0 on the client side and the configured source address or the
+/// unspecified address.
+pub(crate) struct Trace {
+    out: BufWriter<File>,
+    mask_password: bool,
+    peers: Vec<(IpAddr, SocketAddr)>,
+}
+
+impl Trace {
+    pub(crate) fn create(path: &Path, mask_password: bool) -> io::Result<Self> {
+        let file = open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut out = BufWriter::new(file);
+
+        if empty {
+            out.write_all(&PCAP_MAGIC.to_ne_bytes())?;
+            out.write_all(&2u16.to_ne_bytes())?;
+            out.write_all(&4u16.to_ne_bytes())?;
+            out.write_all(&0i32.to_ne_bytes())?;
+            out.write_all(&0u32.to_ne_bytes())?;
+            out.write_all(&PCAP_SNAPLEN.to_ne_bytes())?;
+            out.write_all(&LINKTYPE_RAW.to_ne_bytes())?;
+            out.flush()?;
+        }
+
+        Ok(Trace {
+            out,
+            mask_password,
+            peers: vec![],
+        })
+    }
+
+    /// Register a server, servers are referenced by their index
+    pub(crate) fn add_peer(
+        &mut self,
+        source: Option<IpAddr>,
+        addr: SocketAddr,
+    ) {
+        let local = source.unwrap_or_else(|| match addr {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        });
+        self.peers.push((local, addr));
+    }
+
+    pub(crate) fn packet(
+        &mut self,
+        server: usize,
+        sent: bool,
+        packet: &[u8],
+    ) -> io::Result<()> {
+        let (local, remote) = match self.peers.get(server) {
+            Some(peer) => *peer,
+            None => return Ok(()),
+        };
+
+        let mut payload = packet.to_vec();
+
+        if self.mask_password {
+            mask_password(&mut payload);
+        }
+
+        let (src, dst) = if sent {
+            ((local, 0), (remote.ip(), remote.port()))
+        } else {
+            ((remote.ip(), remote.port()), (local, 0))
+        };
+
+        let frame = ip_frame(src, dst, &payload);
+
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        self.out.write_all(&(ts.as_secs() as u32).to_ne_bytes())?;
+        self.out.write_all(&ts.subsec_micros().to_ne_bytes())?;
+        self.out.write_all(&(frame.len() as u32).to_ne_bytes())?;
+        self.out.write_all(&(frame.len() as u32).to_ne_bytes())?;
+        self.out.write_all(&frame)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+/// Overwrite the values of User-Password and of the password change
+/// attributes, the length is kept so the packet stays valid.
+fn mask_password(packet: &mut [u8]) {
+    let mut pos = RADIUS_HEADER_LEN;
+
+    while pos + 2 <= packet.len() {
+        let len = packet[pos + 1] as usize;
+        if len < 2 || pos + len > packet.len() {
+            return;
+        }
+        let kind = packet[pos];
+        let value = &mut packet[pos + 2..pos + len];
+        match kind {
+            ATTR_USER_PASSWORD => zero(value),
+            ATTR_VENDOR_SPECIFIC => mask_vendor(value),
+            _ => {}
+        }
+        pos += len;
+    }
+}
+
+/// Overwrite the Microsoft password change values of a Vendor-Specific
+/// attribute, which has a vendor id followed by sub-attributes.
+fn mask_vendor(value: &mut [u8]) {
+    if value.len() < 4
+        || u32::from_be_bytes([value[0], value[1], value[2], value[3]])
+            != VENDOR_MICROSOFT
+    {
+        return;
+    }
+
+    let mut pos = 4;
+
+    while pos + 2 <= value.len() {
+        let len = value[pos + 1] as usize;
+        if len < 2 || pos + len > value.len() {
+            return;
+        }
+        if value[pos] == MS_CHAP_NT_ENC_PW || value[pos] == MS_CHAP2_CPW {
+            zero(&mut value[pos + 2..pos + len]);
+        }
+        pos += len;
+    }
+}
+
+fn zero(value: &mut [u8]) {
+    for b in value.iter_mut() {
+        *b = 0;
+    }
+}
+
+fn ip_frame(src: (IpAddr, u16), dst: (IpAddr, u16), payload: &[u8]) -> Vec<u8> {
+    let udp_len = (UDP_HEADER_LEN + payload.len()) as u16;
+
+    let mut frame = match (src.0, dst.0) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            let mut h = vec![0x45, 0];
+            h.extend_from_slice(&(20 + udp_len).to_be_bytes());
+            h.extend_from_slice(&[0, 0, 0x40, 0, 64, IPPROTO_UDP, 0, 0]);
+            h.extend_from_slice(&s.octets());
+            h.extend_from_slice(&d.octets());
+            let checksum = ipv4_checksum(&h);
+            h[10..12].copy_from_slice(&checksum.to_be_bytes());
+            h
+        }
+        (s, d) => {
+            let mut h = vec![0x60, 0, 0, 0];
+            h.extend_from_slice(&udp_len.to_be_bytes());
+            h.extend_from_slice(&[IPPROTO_UDP, 64]);
+            h.extend_from_slice(&to_ipv6(s).octets());
+            h.extend_from_slice(&to_ipv6(d).octets());
+            h
+        }
+    };
+
+    // The UDP checksum is left empty, Wireshark does not validate it by
+    // default
+    frame.extend_from_slice(&src.1.to_be_bytes());
+    frame.extend_from_slice(&dst.1.to_be_bytes());
+    frame.extend_from_slice(&udp_len.to_be_bytes());
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|w| u16::from_be_bytes([w[0], w[1]]) as u32)
+        .sum();
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
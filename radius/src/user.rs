@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use common::serde::{decode_base16, encode_base16};
 
+/// Attribute returned in Access-Accept, standard attributes have vendor 0
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Attribute {
     pub vendor: u32,
@@ -1,8 +1,60 @@
+use std::sync::{Mutex, MutexGuard};
+
 use radius::Config;
 use radius::Error;
+use radius_mock::{Behavior, MockConfig, MockServer, MockUser, Request};
+
+lazy_static::lazy_static! {
+    static ref SERVER: MockServer = MockServer::start(mock_config())
+        .expect("Cannot start mock server");
+    static ref SERIAL: Mutex<()> = Mutex::new(());
+}
+
+fn mock_config() -> MockConfig {
+    MockConfig::new("testing123")
+        .user(MockUser::new("testing", "password").vendor_attribute(
+            1,
+            1,
+            vec![0xAA],
+        ))
+        .user(
+            MockUser::new("classified", "password")
+                .attribute(25, b"ops".to_vec())
+                .attribute(25, b"sre".to_vec())
+                .attribute(11, b"admins".to_vec()),
+        )
+        .user(MockUser::new("dropped", "password").behavior(Behavior::Drop))
+        .user(
+            MockUser::new("bad_authenticator", "password")
+                .behavior(Behavior::BadAuthenticator),
+        )
+        .user(MockUser::new("spoofed", "password").behavior(Behavior::Spoofed))
+        .user(MockUser::new("challenged", "password").challenge("Code:", "42"))
+        .user(MockUser::new("expired", "password").password_expired())
+        .user(MockUser::new("plain", "password"))
+        .reject_message("Invalid credentials")
+}
+
+/// The client state is global, tests must not run concurrently
+pub fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn server_address() -> String {
+    SERVER.addr().to_string()
+}
+
+/// Requests received by the mock server, in order
+pub fn server_requests() -> Vec<Request> {
+    SERVER.requests()
+}
 
 pub fn config() -> Result<Config, Error> {
     let path = std::env::current_dir()?;
     let path = path.join("../tests/config.toml");
-    Config::read_file(path)
+    let mut config = Config::read_file(path)?;
+    for server in config.servers.iter_mut() {
+        server.address = server_address();
+    }
+    Ok(config)
 }
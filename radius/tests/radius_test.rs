@@ -2,7 +2,10 @@
 
 use radius::Credentials;
 use radius::Client;
+use radius::ClientErrorKind;
+use radius::ConfigBuilder;
 use radius::Error;
+use radius::Server;
 
 mod helpers;
 use helpers::*;
@@ -10,6 +13,7 @@ use helpers::*;
 
 #[test]
 fn it_passes_auth() -> Result<(), Error> {
+    let _serial = serial();
     let c = client()?;
     let cred = Credentials::with_username_password("testing", "password");
     let user = c.authenticate(&cred)?;
@@ -21,17 +25,266 @@ fn it_passes_auth() -> Result<(), Error> {
 }
 
 
+#[test]
+fn it_passes_auth_without_attributes() -> Result<(), Error> {
+    let _serial = serial();
+    let c = client()?;
+    let cred = Credentials::with_username_password("plain", "password");
+    let user = c.authenticate(&cred)?;
+    assert!(user.attributes.is_empty());
+    Ok(())
+}
+
+
 #[test]
 fn it_fails_auth() -> Result<(), Error> {
+    let _serial = serial();
     let c = client()?;
     let cred = Credentials::with_username_password("testing", "fail");
     let res = c.authenticate(&cred);
-    assert_matches!(res, Err(Error::AuthReject));
+    assert_matches!(res, Err(Error::AuthReject(response)) => {
+        assert_eq!(response.code, Some(3));
+        assert!(response.server.is_some());
+        assert_eq!(
+            response.reply_message.as_deref(),
+            Some("Invalid credentials")
+        );
+    });
+    Ok(())
+}
+
+
+#[test]
+fn it_rejects_invalid_source_address() -> Result<(), Error> {
+    let _serial = serial();
+    let mut conf = config()?;
+    conf.source_address = Some("not an address".to_string());
+    let res = Client::with_config(&conf).err();
+    assert_matches!(res, Some(Error::InvalidSourceAddress(_)));
+    Ok(())
+}
+
+#[test]
+fn it_sends_address_of_bound_interface() -> Result<(), Error> {
+    let _serial = serial();
+    let mut conf = config()?;
+    conf.bind_interface = Some("lo".to_string());
+    let c = Client::with_config(&conf)?;
+    let cred = Credentials::with_username_password("testing", "password");
+    c.authenticate(&cred)?;
+    let requests = server_requests();
+    let request = requests.last().unwrap();
+    // NAS-IP-Address
+    assert_eq!(request.attribute(4), Some(&[127, 0, 0, 1][..]));
+    Ok(())
+}
+
+#[test]
+fn it_reads_shared_secret_from_env() -> Result<(), Error> {
+    let _serial = serial();
+    let mut conf = config()?;
+    let secret = conf.shared_secret.take().unwrap();
+    std::env::set_var("RADIUS_TEST_SECRET", secret);
+    conf.shared_secret_env = Some("RADIUS_TEST_SECRET".to_string());
+    let c = Client::with_config(&conf)?;
+    let cred = Credentials::with_username_password("testing", "password");
+    c.authenticate(&cred)?;
+
+    conf.shared_secret_env = Some("RADIUS_TEST_MISSING_SECRET".to_string());
+    let res = Client::with_config(&conf).err();
+    assert_matches!(res, Some(Error::SharedSecretEnv(_)));
+    Ok(())
+}
+
+#[test]
+fn it_times_out() -> Result<(), Error> {
+    let _serial = serial();
+    let mut conf = config()?;
+    conf.servers[0].timeout = Some(1);
+    let c = Client::with_config(&conf)?;
+    let cred = Credentials::with_username_password("dropped", "password");
+    let res = c.authenticate(&cred);
+    assert_matches!(res, Err(Error::ServerTimeout(response)) => {
+        assert_eq!(response.code, None);
+    });
+    Ok(())
+}
+
+#[test]
+fn it_rejects_bad_authenticator() -> Result<(), Error> {
+    let _serial = serial();
+    let c = client()?;
+    let cred =
+        Credentials::with_username_password("bad_authenticator", "password");
+    let res = c.authenticate(&cred);
+    assert_matches!(
+        res,
+        Err(Error::RadiusClient {
+            kind: ClientErrorKind::BadAuthenticator,
+            ..
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn it_ignores_spoofed_response() -> Result<(), Error> {
+    let _serial = serial();
+    let c = client()?;
+    let cred = Credentials::with_username_password("spoofed", "password");
+    c.authenticate(&cred)?;
     Ok(())
 }
 
+#[test]
+fn it_rejects_challenge() -> Result<(), Error> {
+    let _serial = serial();
+    let c = client()?;
+    let cred = Credentials::with_username_password("challenged", "password");
+    let res = c.authenticate(&cred);
+    assert_matches!(res, Err(Error::RadiusClient {
+        kind: ClientErrorKind::UnexpectedCode,
+        response,
+    }) => {
+        assert_eq!(response.code, Some(11));
+    });
+    Ok(())
+}
+
+#[test]
+fn it_changes_password() -> Result<(), Error> {
+    let _serial = serial();
+    let c = client()?;
+    let cred = Credentials::with_username_password("expired", "password");
+    let res = c.authenticate(&cred);
+    assert_matches!(res, Err(Error::AuthReject(response)) => {
+        assert!(response.password_expired);
+    });
+
+    let cred = Credentials::with_username_password("expired", "wrong");
+    let res = c.change_password(&cred, "newPass1");
+    assert_matches!(res, Err(Error::AuthReject(response)) => {
+        assert!(!response.password_expired);
+    });
+
+    let cred = Credentials::with_username_password("expired", "password");
+    c.change_password(&cred, "newPass1")?;
+    let res = c.authenticate(&cred);
+    assert_matches!(res, Err(Error::AuthReject(_)));
+    let cred = Credentials::with_username_password("expired", "newPass1");
+    c.authenticate(&cred)?;
+    Ok(())
+}
+
+#[test]
+fn it_writes_trace() -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _serial = serial();
+    let path = std::env::temp_dir()
+        .join(format!("radius_trace_{}.pcap", std::process::id()));
+    let mut conf = config()?;
+    conf.trace_file = Some(path.clone());
+    let cred = Credentials::with_username_password("testing", "password");
+    let c = Client::with_config(&conf)?;
+    c.authenticate(&cred)?;
+    drop(c);
+    let first = std::fs::metadata(&path)?.len() as usize;
+
+    // A second client appends its packets without a new header
+    let c = Client::with_config(&conf)?;
+    c.authenticate(&cred)?;
+    drop(c);
+
+    let mode = std::fs::metadata(&path)?.permissions().mode();
+    let trace = std::fs::read(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(&trace[..4], &0xa1b2_c3d4u32.to_ne_bytes());
+    // Header and one record for the request and the response
+    assert!(first > 24 + 2 * 16);
+    assert_eq!(trace.len(), 2 * first - 24);
+
+    // pcap header, record header, IPv4 and UDP headers
+    let request = &trace[24 + 16 + 20 + 8..];
+    let len = u16::from_be_bytes([request[2], request[3]]) as usize;
+    let mut pos = 20;
+    let mut masked = false;
+    while pos < len {
+        let attr_len = request[pos + 1] as usize;
+        if request[pos] == 2 {
+            masked = request[pos + 2..pos + attr_len].iter().all(|b| *b == 0);
+        }
+        pos += attr_len;
+    }
+    assert!(masked);
+    Ok(())
+}
+
+#[test]
+fn it_builds_config() -> Result<(), Error> {
+    let _serial = serial();
+    let conf = ConfigBuilder::new()
+        .shared_secret("testing123")
+        .server(Server::new(server_address()).with_timeout(3))
+        .attribute(1, 1)
+        .build()?;
+    let c = Client::with_config(&conf)?;
+    let cred = Credentials::with_username_password("testing", "password");
+    let user = c.authenticate(&cred)?;
+    assert_eq!(user.attributes.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn it_returns_standard_attributes() -> Result<(), Error> {
+    let _serial = serial();
+    let conf = ConfigBuilder::new()
+        .shared_secret("testing123")
+        .server(Server::new(server_address()).with_timeout(3))
+        .attribute(0, 25)
+        .attribute(0, 11)
+        .attribute(1, 1)
+        .build()?;
+    let c = Client::with_config(&conf)?;
+    let cred = Credentials::with_username_password("classified", "password");
+    let user = c.authenticate(&cred)?;
+    let attrs: Vec<_> = user
+        .attributes
+        .iter()
+        .map(|attr| (attr.vendor, attr.subtype, attr.data.as_slice()))
+        .collect();
+    assert_eq!(
+        attrs,
+        vec![
+            (0, 25, &b"ops"[..]),
+            (0, 25, &b"sre"[..]),
+            (0, 11, &b"admins"[..]),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn it_reports_every_config_error() {
+    let res = ConfigBuilder::new()
+        .server(Server::new("invalid host name"))
+        .server(Server::new("127.0.0.1").with_shared_secret("x".repeat(300)))
+        .build()
+        .err();
+    assert_matches!(res, Some(Error::InvalidConfig(errors)) => {
+        assert_eq!(errors.len(), 3);
+        assert_matches!(&errors[0], Error::ServerConfig(_, err) => {
+            assert_matches!(**err, Error::NoSharedSecret);
+        });
+        assert_matches!(&errors[1], Error::InvalidServer(_));
+        assert_matches!(&errors[2], Error::ServerConfig(_, err) => {
+            assert_matches!(**err, Error::SharedSecretTooLong);
+        });
+    });
+}
+
 fn client() -> Result<Client, Error> {
     let conf = config()?;
     Client::with_config(&conf)
 }
-
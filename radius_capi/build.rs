@@ -0,0 +1,34 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=include/radius_client.h");
+
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("Cannot read cbindgen.toml");
+
+    // The committed header is only written by `make capi-header`, the build
+    // must not modify the source tree
+    let header = out_dir.join("radius_client.h");
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("Cannot generate C header")
+        .write_to_file(&header);
+
+    let generated = std::fs::read(&header).expect("Cannot read C header");
+    let committed = std::fs::read(crate_dir.join("include/radius_client.h"));
+    if committed.ok().as_ref() != Some(&generated) {
+        println!(
+            "cargo:warning=include/radius_client.h is out of date, run `make \
+             capi-header`"
+        );
+    }
+
+    println!(
+        "cargo:rustc-env=RADIUS_CLIENT_INCLUDE_DIR={}",
+        out_dir.display()
+    );
+}
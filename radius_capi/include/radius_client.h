@@ -0,0 +1,105 @@
+#ifndef RADIUS_CLIENT_H
+#define RADIUS_CLIENT_H
+
+/* Generated by cbindgen from radius_capi, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Result of API calls
+ */
+typedef enum RadiusStatus {
+  RADIUS_STATUS_OK = 0,
+  /*
+   The server rejected the credentials
+   */
+  RADIUS_STATUS_REJECT,
+  /*
+   No server answered
+   */
+  RADIUS_STATUS_TIMEOUT,
+  /*
+   Any other failure, see radius_last_error()
+   */
+  RADIUS_STATUS_ERROR,
+  /*
+   A NULL pointer, an invalid UTF-8 string or an out of range index was
+   given
+   */
+  RADIUS_STATUS_INVALID_ARGUMENT,
+} RadiusStatus;
+
+/*
+ Opaque RADIUS client
+ */
+typedef struct RadiusClient RadiusClient;
+
+/*
+ Opaque authenticated user, holding the requested attributes
+ */
+typedef struct RadiusUser RadiusUser;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Message of the last error that occurred in the calling thread, or NULL.
+
+ The string is owned by the library and valid until the next call in the
+ same thread.
+ */
+const char *radius_last_error(void);
+
+/*
+ Create a client from the [radius] section of a TOML config file.
+
+ Returns NULL on failure.
+ */
+struct RadiusClient *radius_client_new(const char *config_path);
+
+/*
+ Free a client, NULL is ignored
+ */
+void radius_client_free(struct RadiusClient *client);
+
+/*
+ Authenticate a user.
+
+ On RADIUS_STATUS_OK, *user is set to a user that must be freed with
+ radius_user_free(). It is set to NULL otherwise.
+ */
+enum RadiusStatus radius_authenticate(const struct RadiusClient *client,
+                                      const char *username,
+                                      const char *password,
+                                      struct RadiusUser **user);
+
+/*
+ Number of attributes of a user, 0 if user is NULL
+ */
+uintptr_t radius_user_attribute_count(const struct RadiusUser *user);
+
+/*
+ Get the attribute at index, data points into the user and is valid until
+ the user is freed. Standard attributes have vendor 0.
+ */
+enum RadiusStatus radius_user_attribute(const struct RadiusUser *user,
+                                        uintptr_t index,
+                                        uint32_t *vendor,
+                                        uint8_t *subtype,
+                                        const uint8_t **data,
+                                        uintptr_t *len);
+
+/*
+ Free a user, NULL is ignored
+ */
+void radius_user_free(struct RadiusUser *user);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RADIUS_CLIENT_H */
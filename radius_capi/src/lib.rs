@@ -0,0 +1,240 @@
+//! C API over `radius::Client`.
+//!
+//! The header `include/radius_client.h` is generated from this file by
+//! cbindgen with `make capi-header`. Only one client can exist at a time in
+//! a process. A panic never unwinds into the caller, the function fails
+//! instead.
+//!
+//! # Safety
+//!
+//! Pointers given to the API must be NULL or valid: strings are NUL
+//! terminated and clients and users come from this library and were not
+//! freed.
+
+// Safety requirements are shared by every function and documented above
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use radius::{Client, Config, Credentials, Error, User};
+
+/// Opaque RADIUS client
+pub struct RadiusClient {
+    client: Client,
+}
+
+/// Opaque authenticated user, holding the requested attributes
+pub struct RadiusUser {
+    user: User,
+}
+
+/// Result of API calls
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RadiusStatus {
+    Ok = 0,
+    /// The server rejected the credentials
+    Reject,
+    /// No server answered
+    Timeout,
+    /// Any other failure, see radius_last_error()
+    Error,
+    /// A NULL pointer, an invalid UTF-8 string or an out of range index was
+    /// given
+    InvalidArgument,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error<S: ToString>(err: S) {
+    let msg =
+        CString::new(err.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Run the body of an API function, a panic must not unwind into the C
+/// caller. The default is returned and the last error set if it panics.
+fn guard<T, F: FnOnce() -> T>(default: T, f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(_) => {
+            set_last_error("Internal error in the RADIUS client");
+            default
+        }
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Message of the last error that occurred in the calling thread, or NULL.
+///
+/// The string is owned by the library and valid until the next call in the
+/// same thread.
+#[no_mangle]
+pub extern "C" fn radius_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|e| match &*e.borrow() {
+            Some(msg) => msg.as_ptr(),
+            None => ptr::null(),
+        })
+    })
+}
+
+/// Create a client from the [radius] section of a TOML config file.
+///
+/// Returns NULL on failure.
+#[no_mangle]
+pub unsafe extern "C" fn radius_client_new(
+    config_path: *const c_char,
+) -> *mut RadiusClient {
+    guard(ptr::null_mut(), || {
+        let path = match to_str(config_path) {
+            Some(path) => path,
+            None => {
+                set_last_error("Invalid config path");
+                return ptr::null_mut();
+            }
+        };
+
+        let client = Config::read_file(Path::new(path))
+            .and_then(|config| Client::with_config(&config));
+
+        match client {
+            Ok(client) => Box::into_raw(Box::new(RadiusClient { client })),
+            Err(err) => {
+                set_last_error(err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Free a client, NULL is ignored
+#[no_mangle]
+pub unsafe extern "C" fn radius_client_free(client: *mut RadiusClient) {
+    guard((), || {
+        if !client.is_null() {
+            drop(Box::from_raw(client));
+        }
+    })
+}
+
+/// Authenticate a user.
+///
+/// On RADIUS_STATUS_OK, *user is set to a user that must be freed with
+/// radius_user_free(). It is set to NULL otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn radius_authenticate(
+    client: *const RadiusClient,
+    username: *const c_char,
+    password: *const c_char,
+    user: *mut *mut RadiusUser,
+) -> RadiusStatus {
+    guard(RadiusStatus::Error, || {
+        if user.is_null() {
+            return RadiusStatus::InvalidArgument;
+        }
+
+        *user = ptr::null_mut();
+
+        let client = match client.as_ref() {
+            Some(client) => client,
+            None => return RadiusStatus::InvalidArgument,
+        };
+
+        let (username, password) = match (to_str(username), to_str(password)) {
+            (Some(u), Some(p)) => (u, p),
+            _ => return RadiusStatus::InvalidArgument,
+        };
+
+        let cred = Credentials::with_username_password(username, password);
+
+        match client.client.authenticate(&cred) {
+            Ok(u) => {
+                *user = Box::into_raw(Box::new(RadiusUser { user: u }));
+                RadiusStatus::Ok
+            }
+            Err(err) => {
+                let status = match err {
+                    Error::AuthReject(_) => RadiusStatus::Reject,
+                    Error::ServerTimeout(_) => RadiusStatus::Timeout,
+                    _ => RadiusStatus::Error,
+                };
+                set_last_error(err);
+                status
+            }
+        }
+    })
+}
+
+/// Number of attributes of a user, 0 if user is NULL
+#[no_mangle]
+pub unsafe extern "C" fn radius_user_attribute_count(
+    user: *const RadiusUser,
+) -> usize {
+    guard(0, || match user.as_ref() {
+        Some(user) => user.user.attributes.len(),
+        None => 0,
+    })
+}
+
+/// Get the attribute at index, data points into the user and is valid until
+/// the user is freed. Standard attributes have vendor 0.
+#[no_mangle]
+pub unsafe extern "C" fn radius_user_attribute(
+    user: *const RadiusUser,
+    index: usize,
+    vendor: *mut u32,
+    subtype: *mut u8,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> RadiusStatus {
+    guard(RadiusStatus::Error, || {
+        let user = match user.as_ref() {
+            Some(user) => user,
+            None => return RadiusStatus::InvalidArgument,
+        };
+
+        if vendor.is_null()
+            || subtype.is_null()
+            || data.is_null()
+            || len.is_null()
+        {
+            return RadiusStatus::InvalidArgument;
+        }
+
+        let attr = match user.user.attributes.get(index) {
+            Some(attr) => attr,
+            None => return RadiusStatus::InvalidArgument,
+        };
+
+        *vendor = attr.vendor;
+        *subtype = attr.subtype;
+        *data = attr.data.as_ptr();
+        *len = attr.data.len();
+
+        RadiusStatus::Ok
+    })
+}
+
+/// Free a user, NULL is ignored
+#[no_mangle]
+pub unsafe extern "C" fn radius_user_free(user: *mut RadiusUser) {
+    guard((), || {
+        if !user.is_null() {
+            drop(Box::from_raw(user));
+        }
+    })
+}
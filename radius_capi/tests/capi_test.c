@@ -0,0 +1,61 @@
+#include <stdio.h>
+
+#include "radius_client.h"
+
+#define CHECK(cond)                                               \
+  do {                                                            \
+    if (!(cond)) {                                                \
+      fprintf(stderr, "%s:%d: check failed: %s (%s)\n", __FILE__, \
+              __LINE__, #cond,                                    \
+              radius_last_error() ? radius_last_error() : "");    \
+      return 1;                                                   \
+    }                                                             \
+  } while (0)
+
+int main(int argc, char** argv) {
+  RadiusClient* client;
+  RadiusUser* user = NULL;
+  uint32_t vendor;
+  uint8_t subtype;
+  const uint8_t* data;
+  size_t len;
+
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s config.toml\n", argv[0]);
+    return 2;
+  }
+
+  CHECK(radius_client_new("/nonexistent/config.toml") == NULL);
+  CHECK(radius_last_error() != NULL);
+
+  client = radius_client_new(argv[1]);
+  CHECK(client != NULL);
+
+  CHECK(radius_authenticate(client, "testing", "password", &user) ==
+        RADIUS_STATUS_OK);
+  CHECK(user != NULL);
+  CHECK(radius_user_attribute_count(user) == 1);
+
+  CHECK(radius_user_attribute(user, 0, &vendor, &subtype, &data, &len) ==
+        RADIUS_STATUS_OK);
+  CHECK(vendor == 1);
+  CHECK(subtype == 1);
+  CHECK(len == 1);
+  CHECK(data[0] == 0xAA);
+
+  CHECK(radius_user_attribute(user, 1, &vendor, &subtype, &data, &len) ==
+        RADIUS_STATUS_INVALID_ARGUMENT);
+
+  radius_user_free(user);
+
+  CHECK(radius_authenticate(client, "testing", "fail", &user) ==
+        RADIUS_STATUS_REJECT);
+  CHECK(user == NULL);
+
+  CHECK(radius_authenticate(NULL, "testing", "password", &user) ==
+        RADIUS_STATUS_INVALID_ARGUMENT);
+
+  radius_client_free(client);
+
+  return 0;
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+use std::process::Command;
+
+use radius_mock::{MockConfig, MockServer, MockUser};
+
+/// Build tests/capi_test.c against the cdylib and run it with a config
+/// pointing to a mock server.
+#[test]
+fn it_authenticates_from_c() {
+    let server = MockServer::start(
+        MockConfig::new("testing123").user(
+            MockUser::new("testing", "password")
+                .vendor_attribute(1, 1, vec![0xAA]),
+        ),
+    )
+    .unwrap();
+
+    let dir = std::env::temp_dir()
+        .join(format!("radius_capi_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config = dir.join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[radius]
+shared_secret = "testing123"
+attributes = ["1.1"]
+
+[[radius.servers]]
+address = "{}"
+timeout = 3
+"#,
+            server.addr()
+        ),
+    )
+    .unwrap();
+
+    // The cdylib is built in the deps directory of the test binary
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap();
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let bin = dir.join("capi_test");
+
+    let cc = std::env::var_os("CC").unwrap_or_else(|| "cc".into());
+    let status = Command::new(cc)
+        .arg(manifest.join("tests/capi_test.c"))
+        .arg("-I")
+        .arg(env!("RADIUS_CLIENT_INCLUDE_DIR"))
+        .arg("-L")
+        .arg(lib_dir)
+        .arg("-lradius_client")
+        .arg("-o")
+        .arg(&bin)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = Command::new(&bin)
+        .arg(&config)
+        .env("LD_LIBRARY_PATH", lib_dir)
+        .status()
+        .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(status.success());
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+/// How the server replies to requests of a user
+#[derive(Clone, Debug, PartialEq)]
+pub enum Behavior {
+    /// Reply immediately
+    Reply,
+    /// Reply after the given delay
+    Delay(Duration),
+    /// Never reply
+    Drop,
+    /// Reply with a packet whose length does not match its content
+    Malformed,
+    /// Reply with an invalid response authenticator
+    BadAuthenticator,
+    /// Send a reject with an invalid response authenticator, as a forged
+    /// packet would have, before the reply
+    Spoofed,
+}
+
+/// Challenge sent after the password is accepted, the user must answer with
+/// the response as password.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+    pub prompt: String,
+    pub response: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockUser {
+    pub username: String,
+    pub password: String,
+    /// Standard attributes (type, value) added to Access-Accept
+    pub attributes: Vec<(u8, Vec<u8>)>,
+    /// Vendor attributes (vendor, subtype, value) added to Access-Accept
+    pub vendor_attributes: Vec<(u32, u8, Vec<u8>)>,
+    pub reply_message: Option<String>,
+    pub challenge: Option<Challenge>,
+    /// Reject the password with MS-CHAP-Error E=648 until it is changed
+    pub password_expired: bool,
+    pub behavior: Behavior,
+}
+
+impl MockUser {
+    pub fn new<S: Into<String>>(username: S, password: S) -> Self {
+        MockUser {
+            username: username.into(),
+            password: password.into(),
+            attributes: vec![],
+            vendor_attributes: vec![],
+            reply_message: None,
+            challenge: None,
+            password_expired: false,
+            behavior: Behavior::Reply,
+        }
+    }
+
+    pub fn attribute(mut self, attr: u8, value: Vec<u8>) -> Self {
+        self.attributes.push((attr, value));
+        self
+    }
+
+    pub fn vendor_attribute(
+        mut self,
+        vendor: u32,
+        subtype: u8,
+        value: Vec<u8>,
+    ) -> Self {
+        self.vendor_attributes.push((vendor, subtype, value));
+        self
+    }
+
+    pub fn reply_message<S: Into<String>>(mut self, message: S) -> Self {
+        self.reply_message = Some(message.into());
+        self
+    }
+
+    pub fn challenge<S: Into<String>>(
+        mut self,
+        prompt: S,
+        response: S,
+    ) -> Self {
+        self.challenge = Some(Challenge {
+            prompt: prompt.into(),
+            response: response.into(),
+        });
+        self
+    }
+
+    pub fn password_expired(mut self) -> Self {
+        self.password_expired = true;
+        self
+    }
+
+    pub fn behavior(mut self, behavior: Behavior) -> Self {
+        self.behavior = behavior;
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockConfig {
+    pub shared_secret: String,
+    pub users: Vec<MockUser>,
+    /// Reply-Message added to Access-Reject
+    pub reject_message: Option<String>,
+}
+
+impl MockConfig {
+    pub fn new<S: Into<String>>(shared_secret: S) -> Self {
+        MockConfig {
+            shared_secret: shared_secret.into(),
+            users: vec![],
+            reject_message: None,
+        }
+    }
+
+    pub fn user(mut self, user: MockUser) -> Self {
+        self.users.push(user);
+        self
+    }
+
+    pub fn reject_message<S: Into<String>>(mut self, message: S) -> Self {
+        self.reject_message = Some(message.into());
+        self
+    }
+
+    pub(crate) fn find_user(&self, username: &[u8]) -> Option<&MockUser> {
+        self.users
+            .iter()
+            .find(|user| user.username.as_bytes() == username)
+    }
+
+    pub(crate) fn find_user_mut(
+        &mut self,
+        username: &[u8],
+    ) -> Option<&mut MockUser> {
+        self.users
+            .iter_mut()
+            .find(|user| user.username.as_bytes() == username)
+    }
+}
@@ -0,0 +1,15 @@
+//! In-process mock RADIUS server for tests.
+//!
+//! The server listens on an ephemeral UDP port on localhost and answers
+//! requests from a declarative user table. MS-CHAPv2 password changes
+//! replace the password of the user when the old one matches.
+
+mod config;
+mod mschap;
+mod packet;
+mod server;
+
+pub use config::{Behavior, MockConfig, MockUser};
+pub use mschap::encrypt_new_password;
+pub use packet::Request;
+pub use server::MockServer;
@@ -0,0 +1,186 @@
+//! MS-CHAPv2 password change (RFC 2548 and RFC 2759), only what the server
+//! needs to read the new password.
+
+pub(crate) const VENDOR_MICROSOFT: u32 = 311;
+pub(crate) const MS_CHAP_ERROR: u8 = 2;
+pub(crate) const MS_CHAP_NT_ENC_PW: u8 = 6;
+pub(crate) const MS_CHAP2_CPW: u8 = 27;
+
+/// MS-CHAP-Error value of an expired password, after the identifier
+pub(crate) const PASSWORD_EXPIRED: &[u8] = b"\0E=648 R=0 V=3";
+
+/// Length of the encrypted password block and its length field
+const PW_BLOCK_LEN: usize = 516;
+const MAX_PASSWORD_LEN: usize = 512;
+
+/// Read the new password of a change request, from the concatenated
+/// MS-CHAP-NT-Enc-PW chunks encrypted with the hash of the old password.
+/// None if the old password does not match.
+pub(crate) fn decrypt_new_password(
+    enc_pw: &[u8],
+    old_password: &str,
+) -> Option<String> {
+    if enc_pw.len() != PW_BLOCK_LEN {
+        return None;
+    }
+
+    let mut block = enc_pw.to_vec();
+    rc4(&nt_password_hash(old_password), &mut block);
+
+    let len = &block[MAX_PASSWORD_LEN..];
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    if len > MAX_PASSWORD_LEN || len & 1 == 1 {
+        return None;
+    }
+
+    let units: Vec<u16> = block[MAX_PASSWORD_LEN - len..MAX_PASSWORD_LEN]
+        .chunks(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+
+    String::from_utf16(&units).ok()
+}
+
+/// Encrypt a new password like a client does, for tests which build the
+/// requests themselves
+pub fn encrypt_new_password(new_password: &str, old_password: &str) -> Vec<u8> {
+    let units: Vec<u8> = new_password
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes().to_vec())
+        .collect();
+
+    let mut block = vec![0u8; PW_BLOCK_LEN];
+    block[MAX_PASSWORD_LEN - units.len()..MAX_PASSWORD_LEN]
+        .copy_from_slice(&units);
+    block[MAX_PASSWORD_LEN..]
+        .copy_from_slice(&(units.len() as u32).to_le_bytes());
+
+    rc4(&nt_password_hash(old_password), &mut block);
+    block
+}
+
+/// MD4 of the UTF-16LE password
+fn nt_password_hash(password: &str) -> [u8; 16] {
+    let units: Vec<u8> = password
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes().to_vec())
+        .collect();
+    md4(&units)
+}
+
+fn rc4(key: &[u8], data: &mut [u8]) {
+    let mut s: Vec<u8> = (0..=255).collect();
+
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+        s.swap(i, j as usize);
+    }
+
+    let (mut i, mut j) = (0u8, 0u8);
+    for b in data.iter_mut() {
+        i = i.wrapping_add(1);
+        j = j.wrapping_add(s[i as usize]);
+        s.swap(i as usize, j as usize);
+        *b ^= s[s[i as usize].wrapping_add(s[j as usize]) as usize];
+    }
+}
+
+/// MD4 (RFC 1320), still used by MS-CHAP
+fn md4(data: &[u8]) -> [u8; 16] {
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let f = |x: u32, y: u32, z: u32| (x & y) | (!x & z);
+    let g = |x: u32, y: u32, z: u32| (x & y) | (x & z) | (y & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let mut state = [0x6745_2301u32, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    for block in msg.chunks(64) {
+        let x: Vec<u32> = block
+            .chunks(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for &i in [0, 4, 8, 12].iter() {
+            a = a.wrapping_add(f(b, c, d)).wrapping_add(x[i]).rotate_left(3);
+            d = d
+                .wrapping_add(f(a, b, c))
+                .wrapping_add(x[i + 1])
+                .rotate_left(7);
+            c = c
+                .wrapping_add(f(d, a, b))
+                .wrapping_add(x[i + 2])
+                .rotate_left(11);
+            b = b
+                .wrapping_add(f(c, d, a))
+                .wrapping_add(x[i + 3])
+                .rotate_left(19);
+        }
+
+        let k = 0x5a82_7999u32;
+        for i in 0..4 {
+            a = a
+                .wrapping_add(g(b, c, d))
+                .wrapping_add(x[i])
+                .wrapping_add(k)
+                .rotate_left(3);
+            d = d
+                .wrapping_add(g(a, b, c))
+                .wrapping_add(x[i + 4])
+                .wrapping_add(k)
+                .rotate_left(5);
+            c = c
+                .wrapping_add(g(d, a, b))
+                .wrapping_add(x[i + 8])
+                .wrapping_add(k)
+                .rotate_left(9);
+            b = b
+                .wrapping_add(g(c, d, a))
+                .wrapping_add(x[i + 12])
+                .wrapping_add(k)
+                .rotate_left(13);
+        }
+
+        let k = 0x6ed9_eba1u32;
+        for &i in [0, 2, 1, 3].iter() {
+            a = a
+                .wrapping_add(h(b, c, d))
+                .wrapping_add(x[i])
+                .wrapping_add(k)
+                .rotate_left(3);
+            d = d
+                .wrapping_add(h(a, b, c))
+                .wrapping_add(x[i + 8])
+                .wrapping_add(k)
+                .rotate_left(9);
+            c = c
+                .wrapping_add(h(d, a, b))
+                .wrapping_add(x[i + 4])
+                .wrapping_add(k)
+                .rotate_left(11);
+            b = b
+                .wrapping_add(h(c, d, a))
+                .wrapping_add(x[i + 12])
+                .wrapping_add(k)
+                .rotate_left(15);
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0u8; 16];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
@@ -0,0 +1,188 @@
+pub(crate) const ACCESS_REQUEST: u8 = 1;
+pub(crate) const ACCESS_ACCEPT: u8 = 2;
+pub(crate) const ACCESS_REJECT: u8 = 3;
+pub(crate) const ACCOUNTING_REQUEST: u8 = 4;
+pub(crate) const ACCOUNTING_RESPONSE: u8 = 5;
+pub(crate) const ACCESS_CHALLENGE: u8 = 11;
+
+pub(crate) const ATTR_USER_NAME: u8 = 1;
+pub(crate) const ATTR_USER_PASSWORD: u8 = 2;
+pub(crate) const ATTR_REPLY_MESSAGE: u8 = 18;
+pub(crate) const ATTR_STATE: u8 = 24;
+pub(crate) const ATTR_VENDOR_SPECIFIC: u8 = 26;
+
+const HEADER_LEN: usize = 20;
+
+/// Request received by the mock server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub code: u8,
+    pub identifier: u8,
+    pub authenticator: [u8; 16],
+    pub attributes: Vec<(u8, Vec<u8>)>,
+}
+
+impl Request {
+    pub(crate) fn parse(buf: &[u8]) -> Option<Request> {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+
+        let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if len < HEADER_LEN || len > buf.len() {
+            return None;
+        }
+
+        let mut authenticator = [0u8; 16];
+        authenticator.copy_from_slice(&buf[4..HEADER_LEN]);
+
+        let mut attributes = vec![];
+        let mut pos = HEADER_LEN;
+
+        while pos < len {
+            if pos + 2 > len {
+                return None;
+            }
+            let attr_len = buf[pos + 1] as usize;
+            if attr_len < 2 || pos + attr_len > len {
+                return None;
+            }
+            attributes.push((buf[pos], buf[pos + 2..pos + attr_len].to_vec()));
+            pos += attr_len;
+        }
+
+        Some(Request {
+            code: buf[0],
+            identifier: buf[1],
+            authenticator,
+            attributes,
+        })
+    }
+
+    /// First attribute of the given type
+    pub fn attribute(&self, attr: u8) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|(t, _)| *t == attr)
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Values of the vendor attributes of the given vendor and type
+    pub fn vendor_attributes(&self, vendor: u32, subtype: u8) -> Vec<&[u8]> {
+        self.attributes
+            .iter()
+            .filter(|(t, _)| *t == ATTR_VENDOR_SPECIFIC)
+            .filter_map(|(_, v)| {
+                if v.len() < 6 || v[..4] != vendor.to_be_bytes() {
+                    return None;
+                }
+                let len = v[5] as usize;
+                if v[4] != subtype || len < 2 || 4 + len > v.len() {
+                    return None;
+                }
+                Some(&v[6..4 + len])
+            })
+            .collect()
+    }
+
+    pub fn username(&self) -> Option<String> {
+        self.attribute(ATTR_USER_NAME)
+            .map(|u| String::from_utf8_lossy(u).into_owned())
+    }
+
+    /// Decrypt the User-Password attribute (RFC 2865 section 5.2)
+    pub(crate) fn password(&self, secret: &[u8]) -> Option<Vec<u8>> {
+        let data = self.attribute(ATTR_USER_PASSWORD)?;
+
+        if data.is_empty() || data.len() % 16 != 0 {
+            return None;
+        }
+
+        let mut password = vec![];
+        let mut prev = &self.authenticator[..];
+
+        for chunk in data.chunks(16) {
+            let mut ctx = md5::Context::new();
+            ctx.consume(secret);
+            ctx.consume(prev);
+            let hash = ctx.compute();
+            password.extend(chunk.iter().zip(hash.iter()).map(|(c, h)| c ^ h));
+            prev = chunk;
+        }
+
+        while password.last() == Some(&0) {
+            password.pop();
+        }
+
+        Some(password)
+    }
+
+    /// Check the request authenticator of accounting requests (RFC 2866)
+    pub(crate) fn verify_accounting(&self, raw: &[u8], secret: &[u8]) -> bool {
+        let len = u16::from_be_bytes([raw[2], raw[3]]) as usize;
+        let mut ctx = md5::Context::new();
+        ctx.consume(&raw[..4]);
+        ctx.consume([0u8; 16]);
+        ctx.consume(&raw[HEADER_LEN..len]);
+        ctx.consume(secret);
+        ctx.compute().0 == self.authenticator
+    }
+}
+
+/// Response sent by the mock server
+pub(crate) struct Response {
+    pub(crate) code: u8,
+    pub(crate) attributes: Vec<(u8, Vec<u8>)>,
+}
+
+impl Response {
+    pub(crate) fn new(code: u8) -> Self {
+        Response {
+            code,
+            attributes: vec![],
+        }
+    }
+
+    pub(crate) fn add(&mut self, attr: u8, value: &[u8]) {
+        self.attributes.push((attr, value.to_vec()));
+    }
+
+    pub(crate) fn add_vendor(
+        &mut self,
+        vendor: u32,
+        subtype: u8,
+        value: &[u8],
+    ) {
+        let mut data = vendor.to_be_bytes().to_vec();
+        data.push(subtype);
+        data.push(value.len() as u8 + 2);
+        data.extend_from_slice(value);
+        self.attributes.push((ATTR_VENDOR_SPECIFIC, data));
+    }
+
+    /// Encode the response, the response authenticator is computed from the
+    /// request authenticator and the shared secret.
+    pub(crate) fn encode(&self, request: &Request, secret: &[u8]) -> Vec<u8> {
+        let mut attrs = vec![];
+        for (attr, value) in self.attributes.iter() {
+            attrs.push(*attr);
+            attrs.push(value.len() as u8 + 2);
+            attrs.extend_from_slice(value);
+        }
+
+        let len = (HEADER_LEN + attrs.len()) as u16;
+
+        let mut buf = vec![self.code, request.identifier];
+        buf.extend_from_slice(&len.to_be_bytes());
+
+        let mut ctx = md5::Context::new();
+        ctx.consume(&buf);
+        ctx.consume(request.authenticator);
+        ctx.consume(&attrs);
+        ctx.consume(secret);
+        buf.extend_from_slice(&ctx.compute().0);
+
+        buf.extend(attrs);
+        buf
+    }
+}
@@ -0,0 +1,263 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::config::{Behavior, MockConfig, MockUser};
+use crate::mschap::*;
+use crate::packet::*;
+
+const STATE: &[u8] = b"radius_mock";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Mock RADIUS server running in a background thread until dropped
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start(config: MockConfig) -> io::Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let addr = socket.local_addr()?;
+        let requests = Arc::new(Mutex::new(vec![]));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let requests = requests.clone();
+            let stop = stop.clone();
+            thread::spawn(move || run(socket, config, requests, stop))
+        };
+
+        Ok(MockServer {
+            addr,
+            requests,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    socket: UdpSocket,
+    mut config: MockConfig,
+    requests: Arc<Mutex<Vec<Request>>>,
+    stop: Arc<AtomicBool>,
+) {
+    let secret = config.shared_secret.clone().into_bytes();
+    let secret = &secret[..];
+    let mut buf = [0u8; 4096];
+
+    while !stop.load(Ordering::SeqCst) {
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(res) => res,
+            Err(_) => continue,
+        };
+
+        let raw = &buf[..len];
+
+        let request = match Request::parse(raw) {
+            Some(request) => request,
+            None => continue,
+        };
+
+        requests.lock().unwrap().push(request.clone());
+
+        let (response, behavior) = match request.code {
+            ACCESS_REQUEST
+                if !request
+                    .vendor_attributes(VENDOR_MICROSOFT, MS_CHAP2_CPW)
+                    .is_empty() =>
+            {
+                change_password(&mut config, &request)
+            }
+            ACCESS_REQUEST => handle_access(&config, &request),
+            ACCOUNTING_REQUEST => {
+                if !request.verify_accounting(raw, secret) {
+                    continue;
+                }
+                (Response::new(ACCOUNTING_RESPONSE), Behavior::Reply)
+            }
+            _ => continue,
+        };
+
+        let mut reply = response.encode(&request, secret);
+
+        match behavior {
+            Behavior::Reply => {}
+            Behavior::Drop => continue,
+            Behavior::Delay(delay) => {
+                if let Ok(socket) = socket.try_clone() {
+                    thread::spawn(move || {
+                        thread::sleep(delay);
+                        let _ = socket.send_to(&reply, from);
+                    });
+                }
+                continue;
+            }
+            Behavior::Malformed => {
+                let len = reply.len() as u16 + 16;
+                reply[2..4].copy_from_slice(&len.to_be_bytes());
+            }
+            Behavior::BadAuthenticator => {
+                for b in reply[4..20].iter_mut() {
+                    *b = !*b;
+                }
+            }
+            Behavior::Spoofed => {
+                let mut forged = reply.clone();
+                forged[0] = ACCESS_REJECT;
+                for b in forged[4..20].iter_mut() {
+                    *b = !*b;
+                }
+                let _ = socket.send_to(&forged, from);
+            }
+        }
+
+        let _ = socket.send_to(&reply, from);
+    }
+}
+
+fn handle_access(
+    config: &MockConfig,
+    request: &Request,
+) -> (Response, Behavior) {
+    let user = request
+        .attribute(ATTR_USER_NAME)
+        .and_then(|username| config.find_user(username));
+
+    let user = match user {
+        Some(user) => user,
+        None => return (reject(config), Behavior::Reply),
+    };
+
+    let password = request.password(config.shared_secret.as_bytes());
+
+    let response = match (&user.challenge, request.attribute(ATTR_STATE)) {
+        (Some(challenge), Some(STATE)) => {
+            if password.as_deref() == Some(challenge.response.as_bytes()) {
+                accept(user)
+            } else {
+                reject(config)
+            }
+        }
+        (Some(challenge), _)
+            if password.as_deref() == Some(user.password.as_bytes()) =>
+        {
+            let mut response = Response::new(ACCESS_CHALLENGE);
+            response.add(ATTR_STATE, STATE);
+            response.add(ATTR_REPLY_MESSAGE, challenge.prompt.as_bytes());
+            response
+        }
+        (None, _)
+            if password.as_deref() == Some(user.password.as_bytes())
+                && user.password_expired =>
+        {
+            let mut response = reject(config);
+            response.add_vendor(
+                VENDOR_MICROSOFT,
+                MS_CHAP_ERROR,
+                PASSWORD_EXPIRED,
+            );
+            response
+        }
+        (None, _) if password.as_deref() == Some(user.password.as_bytes()) => {
+            accept(user)
+        }
+        _ => reject(config),
+    };
+
+    (response, user.behavior.clone())
+}
+
+/// MS-CHAPv2 password change, the password of the user is replaced when the
+/// new one can be decrypted with the old one. The other fields of
+/// MS-CHAP2-CPW are not checked.
+fn change_password(
+    config: &mut MockConfig,
+    request: &Request,
+) -> (Response, Behavior) {
+    let mut chunks: Vec<&[u8]> = request
+        .vendor_attributes(VENDOR_MICROSOFT, MS_CHAP_NT_ENC_PW)
+        .into_iter()
+        .filter(|chunk| chunk.len() >= 4)
+        .collect();
+    chunks.sort_by_key(|chunk| u16::from_be_bytes([chunk[2], chunk[3]]));
+    let enc_pw: Vec<u8> = chunks
+        .iter()
+        .flat_map(|chunk| chunk[4..].to_vec())
+        .collect();
+
+    let rejected = reject(config);
+
+    let user = request
+        .attribute(ATTR_USER_NAME)
+        .and_then(|username| config.find_user_mut(username));
+
+    let user = match user {
+        Some(user) => user,
+        None => return (rejected, Behavior::Reply),
+    };
+
+    match decrypt_new_password(&enc_pw, &user.password) {
+        Some(password) => {
+            user.password = password;
+            user.password_expired = false;
+            (Response::new(ACCESS_ACCEPT), user.behavior.clone())
+        }
+        None => (rejected, user.behavior.clone()),
+    }
+}
+
+fn accept(user: &MockUser) -> Response {
+    let mut response = Response::new(ACCESS_ACCEPT);
+
+    for (attr, value) in user.attributes.iter() {
+        response.add(*attr, value);
+    }
+
+    for (vendor, subtype, value) in user.vendor_attributes.iter() {
+        response.add_vendor(*vendor, *subtype, value);
+    }
+
+    if let Some(message) = &user.reply_message {
+        response.add(ATTR_REPLY_MESSAGE, message.as_bytes());
+    }
+
+    response
+}
+
+fn reject(config: &MockConfig) -> Response {
+    let mut response = Response::new(ACCESS_REJECT);
+
+    if let Some(message) = &config.reject_message {
+        response.add(ATTR_REPLY_MESSAGE, message.as_bytes());
+    }
+
+    response
+}
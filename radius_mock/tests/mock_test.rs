@@ -0,0 +1,165 @@
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use radius_mock::{
+    encrypt_new_password, Behavior, MockConfig, MockServer, MockUser,
+};
+
+const SECRET: &[u8] = b"testing123";
+const AUTHENTICATOR: [u8; 16] = [7; 16];
+
+fn server() -> MockServer {
+    let config = MockConfig::new("testing123")
+        .user(MockUser::new("testing", "password").vendor_attribute(
+            1,
+            1,
+            vec![0xAA],
+        ))
+        .user(MockUser::new("expired", "password").password_expired())
+        .user(MockUser::new("dropped", "password").behavior(Behavior::Drop))
+        .user(
+            MockUser::new("malformed", "password")
+                .behavior(Behavior::Malformed),
+        )
+        .reject_message("Invalid credentials");
+    MockServer::start(config).unwrap()
+}
+
+fn access_request(username: &str, password: &str) -> Vec<u8> {
+    let mut padded = password.as_bytes().to_vec();
+    padded.resize(16, 0);
+
+    let mut ctx = md5::Context::new();
+    ctx.consume(SECRET);
+    ctx.consume(AUTHENTICATOR);
+    let hash = ctx.compute();
+    let hidden: Vec<u8> =
+        padded.iter().zip(hash.iter()).map(|(p, h)| p ^ h).collect();
+
+    let mut attrs = vec![1, username.len() as u8 + 2];
+    attrs.extend_from_slice(username.as_bytes());
+    attrs.extend_from_slice(&[2, hidden.len() as u8 + 2]);
+    attrs.extend(hidden);
+
+    let mut buf = vec![1, 42];
+    buf.extend_from_slice(&(20 + attrs.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&AUTHENTICATOR);
+    buf.extend(attrs);
+    buf
+}
+
+/// MS-CHAPv2 password change, the way the radius crate sends it
+fn change_password_request(username: &str, old: &str, new: &str) -> Vec<u8> {
+    let vsa = |subtype: u8, data: &[u8]| {
+        let mut attr = vec![26, data.len() as u8 + 8, 0, 0, 1, 55];
+        attr.extend_from_slice(&[subtype, data.len() as u8 + 2]);
+        attr.extend_from_slice(data);
+        attr
+    };
+
+    let mut attrs = vec![1, username.len() as u8 + 2];
+    attrs.extend_from_slice(username.as_bytes());
+    attrs.extend(vsa(27, &[7; 68]));
+    let enc_pw = encrypt_new_password(new, old);
+    for (i, chunk) in enc_pw.chunks(243).enumerate() {
+        let mut data = vec![6, 7, 0, i as u8 + 1];
+        data.extend_from_slice(chunk);
+        attrs.extend(vsa(6, &data));
+    }
+
+    let mut buf = vec![1, 43];
+    buf.extend_from_slice(&(20 + attrs.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&AUTHENTICATOR);
+    buf.extend(attrs);
+    buf
+}
+
+fn exchange(server: &MockServer, request: &[u8]) -> Option<Vec<u8>> {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    socket.send_to(request, server.addr()).unwrap();
+    let mut buf = [0u8; 4096];
+    let (len, _) = socket.recv_from(&mut buf).ok()?;
+    Some(buf[..len].to_vec())
+}
+
+#[test]
+fn it_accepts_user() {
+    let server = server();
+    let reply = exchange(&server, &access_request("testing", "password"));
+    let reply = reply.unwrap();
+
+    assert_eq!(reply[0], 2);
+    assert_eq!(reply[1], 42);
+    // Vendor-Specific with vendor 1, subtype 1 and value 0xAA
+    assert_eq!(&reply[20..], &[26, 9, 0, 0, 0, 1, 1, 3, 0xAA]);
+
+    let mut ctx = md5::Context::new();
+    ctx.consume(&reply[..4]);
+    ctx.consume(AUTHENTICATOR);
+    ctx.consume(&reply[20..]);
+    ctx.consume(SECRET);
+    assert_eq!(&reply[4..20], &ctx.compute().0);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].username().as_deref(), Some("testing"));
+}
+
+#[test]
+fn it_rejects_user() {
+    let server = server();
+    let reply = exchange(&server, &access_request("testing", "fail"));
+    let reply = reply.unwrap();
+
+    assert_eq!(reply[0], 3);
+    assert_eq!(reply[20], 18);
+    assert_eq!(&reply[22..], b"Invalid credentials");
+}
+
+#[test]
+fn it_rejects_expired_password() {
+    let server = server();
+    let reply = exchange(&server, &access_request("expired", "password"));
+    let reply = reply.unwrap();
+
+    assert_eq!(reply[0], 3);
+    // Reply-Message, then MS-CHAP-Error of vendor 311
+    let vsa = &reply[22 + b"Invalid credentials".len()..];
+    assert_eq!(&vsa[..8], &[26, 22, 0, 0, 1, 55, 2, 16]);
+    assert_eq!(&vsa[9..], b"E=648 R=0 V=3");
+
+    let request = change_password_request("expired", "password", "newPass1");
+    assert_eq!(exchange(&server, &request).unwrap()[0], 2);
+    let reply = exchange(&server, &access_request("expired", "newPass1"));
+    assert_eq!(reply.unwrap()[0], 2);
+}
+
+#[test]
+fn it_changes_password() {
+    let server = server();
+    let request = change_password_request("testing", "wrong", "newPass1");
+    assert_eq!(exchange(&server, &request).unwrap()[0], 3);
+
+    let request = change_password_request("testing", "password", "newPass1");
+    assert_eq!(exchange(&server, &request).unwrap()[0], 2);
+
+    let reply = exchange(&server, &access_request("testing", "password"));
+    assert_eq!(reply.unwrap()[0], 3);
+    let reply = exchange(&server, &access_request("testing", "newPass1"));
+    assert_eq!(reply.unwrap()[0], 2);
+}
+
+#[test]
+fn it_misbehaves() {
+    let server = server();
+    let reply = exchange(&server, &access_request("dropped", "password"));
+    assert_eq!(reply, None);
+
+    let reply = exchange(&server, &access_request("malformed", "password"));
+    let reply = reply.unwrap();
+    let len = u16::from_be_bytes([reply[2], reply[3]]) as usize;
+    assert_ne!(len, reply.len());
+}
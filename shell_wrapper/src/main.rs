@@ -1,7 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use nix::sys::signal::{
+    kill, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal,
+};
+use nix::unistd::Pid;
 use nss_db::Config;
 use nss_db::Db;
-use std::process::Command;
+use nss_db::User;
+use std::os::raw::c_int;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
 
 fn main() -> Result<()> {
     let username =
@@ -14,7 +22,7 @@ fn main() -> Result<()> {
     nix::unistd::setuid(root).context("Cannot escalate privileges")?;
 
     let config = Config::system().context("Cannot read system config file")?;
-    let db = Db::with_config(&config)
+    let mut db = Db::with_config(&config)
         .context("Cannot initialize database with config")?;
 
     let user = db
@@ -23,19 +31,49 @@ fn main() -> Result<()> {
 
     std::env::remove_var("RADIUS_USER_COOKIE");
 
-    let uid = nix::unistd::Uid::from_raw(user.mapping.uid);
-    let gid = nix::unistd::Gid::from_raw(user.mapping.gid);
+    let mapping = &user.mapping;
+    let (uid, gid, home, shell) =
+        match (mapping.uid, mapping.gid, &mapping.home, &mapping.shell) {
+            (Some(uid), Some(gid), Some(home), Some(shell)) => {
+                (uid, gid, home.clone(), shell.clone())
+            }
+            _ => bail!("Mapping of {} is not resolved", mapping.username),
+        };
 
-    nix::unistd::setgid(gid).context("Cannot set group")?;
-    nix::unistd::setuid(uid).context("Cannot set user")?;
-    let home: std::path::PathBuf = user.mapping.home.into();
-    nix::unistd::chdir(&home).context("Cannot change directory")?;
+    if let Some(max) = user.account.max_sessions {
+        let running = db
+            .find_sessions(&user.radius.username)
+            .context("Cannot read sessions")?
+            .iter()
+            .filter(|session| session.is_running())
+            .count();
+        if running >= max as usize {
+            bail!("Too many sessions for {}", user.radius.username);
+        }
+    }
+
+    let session = db
+        .store_session(&user.radius.username, std::process::id())
+        .context("Cannot register session")?;
+
+    std::env::set_var("RADIUS_SESSION_ID", &session.session_id);
+
+    let uid = nix::unistd::Uid::from_raw(uid);
+    let gid = nix::unistd::Gid::from_raw(gid);
+    let groups: Vec<_> = user
+        .account
+        .groups
+        .iter()
+        .map(|group| nix::unistd::Gid::from_raw(group.gid))
+        .collect();
+    let home: std::path::PathBuf = home.into();
 
     std::env::remove_var("RADIUS_USER_COOKIE");
 
-    std::env::set_var("HOME", home);
+    std::env::set_var("HOME", &home);
     std::env::set_var("USER", &user.mapping.username);
     std::env::set_var("LOGNAME", &user.mapping.username);
+    std::env::set_var("SHELL", &shell);
 
     if let Ok(path) = std::env::var("MAIL") {
         let path: std::path::PathBuf = path.into();
@@ -44,10 +82,75 @@ fn main() -> Result<()> {
         std::env::set_var("MAIL", path);
     }
 
-    let mut shell = Command::new(user.mapping.shell)
+    // The wrapper stays root to remove the session when the shell exits, a
+    // hang up of the login must not kill it before.
+    let action = SigAction::new(
+        SigHandler::Handler(ignore_signal),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    unsafe { sigaction(Signal::SIGHUP, &action) }
+        .context("Cannot handle hang up")?;
+
+    let mut command = Command::new(shell);
+    unsafe {
+        command.pre_exec(move || {
+            if !groups.is_empty() {
+                nix::unistd::setgroups(&groups).map_err(to_io)?;
+            }
+            nix::unistd::setgid(gid).map_err(to_io)?;
+            nix::unistd::setuid(uid).map_err(to_io)?;
+            nix::unistd::chdir(&home).map_err(to_io)
+        });
+    }
+
+    let result = command
         .spawn()
-        .context("Shell failed to start")?;
+        .context("Shell failed to start")
+        .and_then(|mut shell| wait(&mut shell, &db, &user));
 
-    shell.wait().context("Failed to wait on shell")?;
+    db.delete_session(&session.session_id)
+        .context("Cannot remove session")?;
+    result
+}
+
+/// The handler is reset when the shell is executed, unlike an ignored signal
+extern "C" fn ignore_signal(_: c_int) {}
+
+fn to_io(err: nix::Error) -> std::io::Error {
+    match err.as_errno() {
+        Some(errno) => std::io::Error::from_raw_os_error(errno as i32),
+        None => {
+            std::io::Error::from_raw_os_error(nix::errno::Errno::EINVAL as i32)
+        }
+    }
+}
+
+/// Wait on the shell, it is hung up when the session timeout expires. The
+/// timeout is read again from the database, a CoA request can change it.
+fn wait(shell: &mut Child, db: &Db, user: &User) -> Result<()> {
+    let started = Instant::now();
+    let mut timeout = user.account.session_timeout;
+
+    while shell
+        .try_wait()
+        .context("Failed to wait on shell")?
+        .is_none()
+    {
+        if let Ok(user) = db.get_user(&user.radius.username) {
+            timeout = user.account.session_timeout;
+        }
+        let expired = match timeout {
+            Some(timeout) => started.elapsed() >= Duration::from_secs(timeout),
+            None => false,
+        };
+        if expired {
+            let pid = Pid::from_raw(shell.id() as i32);
+            kill(pid, Signal::SIGHUP).context("Cannot hang up shell")?;
+            shell.wait().context("Failed to wait on shell")?;
+            break;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
     Ok(())
 }
@@ -0,0 +1,38 @@
+#!/bin/sh
+# Mapping command used by the nss_db tests, it maps the user named in its
+# input
+case "$(cat)" in
+  *'"username":"alice"'*)
+    echo '{"username": "ops", "uid": 1020, "group": "ops", "gid": 1020,' \
+      '"home": "/home/ops", "shell": "/bin/bash"}'
+    ;;
+  *'"username":"bob@example.com"'*)
+    exit 1
+    ;;
+  *'"username":"carol"'*)
+    echo '{"username": "ops", "priority": 10}'
+    ;;
+  *'"username":"frank"'*)
+    echo '{"username": "ops", "uid": 0, "group": "root", "gid": 0,' \
+      '"home": "/root", "shell": "/bin/bash"}'
+    ;;
+  *'"username":"grace"'*|*'"username":"mallory/x"'*)
+    echo '{"username": "ops", "uid": 1020, "group": "ops", "gid": 1020,' \
+      '"home": "/home/%u", "shell": "/bin/bash"}'
+    ;;
+  *'"username":"oscar"'*)
+    echo '{"username": "ops", "uid": 1020, "group": "ops", "gid": 1020,' \
+      '"home": "/home/../root", "shell": "/bin/bash"}'
+    ;;
+  *'"username":"peggy"'*)
+    echo '{"username": "ops", "uid": 1020, "group": "ops", "gid": 1020,' \
+      '"home": "/home/ops", "shell": "/bin/ba\u0007sh"}'
+    ;;
+  *'"username":"dave"'*)
+    sleep 10
+    ;;
+  *)
+    echo "unknown user" >&2
+    exit 3
+    ;;
+esac